@@ -1,4 +1,7 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+};
 
 use egui::{
     text::LayoutJob,
@@ -6,43 +9,241 @@ use egui::{
     Color32, TextFormat,
 };
 
-use crate::assembler::lexer::{Lexeme, LexemeKind, Lexer};
+use crate::{
+    assembler::lexer::{Lexeme, LexemeKind, Lexer},
+    simulator::Registers,
+};
 
 #[derive(Default)]
 struct Highlighting;
 
+/// The colors used to highlight each kind of lexeme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HighlightTheme {
+    pub comment: Color32,
+    pub imm: Color32,
+    pub inst: Color32,
+    pub label: Color32,
+    pub punct: Color32,
+    pub group: Color32,
+    pub reg: Color32,
+    pub sect: Color32,
+}
+
+impl HighlightTheme {
+    /// Colors for a dark background.
+    pub const DARK: Self = Self {
+        comment: Color32::DARK_GRAY,
+        imm: Color32::LIGHT_GREEN,
+        inst: Color32::GOLD,
+        label: Color32::from_rgb(0x46, 0x80, 0xc4),
+        punct: Color32::GRAY,
+        group: Color32::from_rgb(0xe0, 0xb0, 0x60),
+        reg: Color32::from_rgb(0x9c, 0xdc, 0xfe),
+        sect: Color32::from_rgb(0xc5, 0x86, 0xc0),
+    };
+
+    /// Colors for a light background.
+    pub const LIGHT: Self = Self {
+        comment: Color32::from_rgb(0x80, 0x80, 0x80),
+        imm: Color32::from_rgb(0x09, 0x86, 0x58),
+        inst: Color32::from_rgb(0xa0, 0x60, 0x00),
+        label: Color32::from_rgb(0x26, 0x5a, 0xa0),
+        punct: Color32::from_rgb(0x50, 0x50, 0x50),
+        group: Color32::from_rgb(0x90, 0x50, 0x00),
+        reg: Color32::from_rgb(0x00, 0x10, 0x80),
+        sect: Color32::from_rgb(0xaf, 0x00, 0xdb),
+    };
+}
+
+impl HighlightTheme {
+    /// The name and color of each kind of lexeme, in display order.
+    pub fn colors(&self) -> [(&'static str, Color32); 8] {
+        let mut theme = *self;
+        theme.colors_mut().map(|(name, color)| (name, *color))
+    }
+
+    /// The name and color of each kind of lexeme, in display order, mutably.
+    pub fn colors_mut(&mut self) -> [(&'static str, &mut Color32); 8] {
+        [
+            ("inst", &mut self.inst),
+            ("reg", &mut self.reg),
+            ("imm", &mut self.imm),
+            ("label", &mut self.label),
+            ("sect", &mut self.sect),
+            ("punct", &mut self.punct),
+            ("group", &mut self.group),
+            ("comment", &mut self.comment),
+        ]
+    }
+}
+
 impl LexemeKind {
-    pub fn into_text_format(self) -> TextFormat {
+    pub fn into_text_format(self, theme: &HighlightTheme) -> TextFormat {
         let font_id = egui::FontId::monospace(12.0);
-        match self {
-            Self::Comment => TextFormat::simple(font_id, Color32::DARK_GRAY),
-            Self::Imm => TextFormat::simple(font_id, Color32::LIGHT_GREEN),
-            Self::Inst => TextFormat::simple(font_id, Color32::GOLD),
-            Self::Label => TextFormat::simple(font_id, Color32::from_rgb(0x46, 0x80, 0xc4)),
-            Self::Punct | Self::Whitespace => TextFormat::simple(font_id, Color32::GRAY),
-            Self::Reg => TextFormat::simple(font_id, Color32::from_rgb(0x9c, 0xdc, 0xfe)),
-            Self::Sect => TextFormat::simple(font_id, Color32::from_rgb(0xc5, 0x86, 0xc0)),
+        let color = match self {
+            Self::Comment => theme.comment,
+            Self::Imm => theme.imm,
+            Self::Inst => theme.inst,
+            Self::Label => theme.label,
+            Self::Punct | Self::Whitespace => theme.punct,
+            Self::Reg => theme.reg,
+            Self::Sect => theme.sect,
+        };
+
+        TextFormat::simple(font_id, color)
+    }
+
+    /// The text format of a lexeme of this kind with the text `text`. The
+    /// parentheses of load-store operands get their own color, so the offset
+    /// and base register read as one group.
+    pub fn text_format_for(self, text: &str, theme: &HighlightTheme) -> TextFormat {
+        let mut format = self.into_text_format(theme);
+        if self == Self::Punct && matches!(text, "(" | ")") {
+            format.color = theme.group;
         }
+
+        format
     }
 }
 
+/// Append the text of a lexeme to `job`, tinting tabs and, for whitespace
+/// lexemes, whitespace at the end of a line. `at_end` is whether the lexeme
+/// ends the text.
+fn append_marking_whitespace(
+    job: &mut LayoutJob,
+    text: &str,
+    format: TextFormat,
+    kind: LexemeKind,
+    at_end: bool,
+    theme: &HighlightTheme,
+) {
+    let marked_format = TextFormat {
+        background: theme.punct.linear_multiply(0.15),
+        ..format.clone()
+    };
+
+    // split the text into runs that are all marked or all unmarked
+    let mut start = 0;
+    let mut marked = None;
+    for (i, c) in text.char_indices() {
+        // line endings, including the `\r` of CRLF, are not marked
+        let trailing = kind == LexemeKind::Whitespace
+            && c != '\n'
+            && c != '\r'
+            && (at_end || text[i..].contains('\n'));
+        let mark = c == '\t' || trailing;
+
+        if let Some(previous) = marked.filter(|previous| *previous != mark) {
+            let format = if previous { &marked_format } else { &format };
+            job.append(&text[start..i], 0.0, format.clone());
+            start = i;
+        }
+        marked = Some(mark);
+    }
+
+    let format = if marked == Some(true) {
+        marked_format
+    } else {
+        format
+    };
+    job.append(&text[start..], 0.0, format);
+}
+
+/// Optional ways of highlighting, on top of the colors of the theme.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HighlightOptions {
+    /// Tint tabs and trailing whitespace with a faint background.
+    pub show_whitespace: bool,
+
+    /// Fade registers by how often they are referenced, so the most used
+    /// ones stand out.
+    pub register_heatmap: bool,
+}
+
+/// The register a register lexeme names, if it is a general purpose one.
+fn lexeme_register(text: &str) -> Option<usize> {
+    text.strip_prefix('$')
+        .and_then(|name| Registers::index(&name.to_ascii_lowercase()))
+        .filter(|index| *index < 32)
+}
+
+/// The number of references to each register in `lexemes`. Counted from the
+/// lexemes rather than the parsed program, so the heatmap holds while the
+/// source has errors.
+fn register_counts(text: &str, lexemes: &[Lexeme]) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    for lexeme in lexemes.iter().filter(|l| l.kind == LexemeKind::Reg) {
+        if let Some(index) = lexeme_register(&text[lexeme.slice.clone()]) {
+            *counts.entry(index).or_insert(0) += 1;
+        }
+    }
+
+    counts
+}
+
 pub type HighlightingCtx = (LayoutJob, BTreeMap<usize, Lexeme>);
 type HighlightingCache = FrameCache<HighlightingCtx, Highlighting>;
 
-impl ComputerMut<&str, HighlightingCtx> for Highlighting {
-    fn compute(&mut self, key: &str) -> HighlightingCtx {
+/// The key of the highlighting cache. Only the text and the theme version
+/// are hashed, so the version must change whenever the theme does.
+#[derive(Clone, Copy)]
+struct HighlightingKey<'a> {
+    text: &'a str,
+    theme: &'a HighlightTheme,
+    version: u64,
+    options: HighlightOptions,
+}
+
+impl Hash for HighlightingKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+        self.version.hash(state);
+        self.options.hash(state);
+    }
+}
+
+impl ComputerMut<HighlightingKey<'_>, HighlightingCtx> for Highlighting {
+    fn compute(&mut self, key: HighlightingKey<'_>) -> HighlightingCtx {
+        let HighlightingKey {
+            text: key,
+            theme,
+            options,
+            ..
+        } = key;
+
         let mut job = LayoutJob::default();
         let lexemes = Lexer::new(key)
             .with_comments(true)
             .with_whitespace(true)
             .lex();
 
+        let counts = if options.register_heatmap {
+            register_counts(key, &lexemes)
+        } else {
+            HashMap::new()
+        };
+        let max_count = counts.values().copied().max().unwrap_or(1);
+
         for lexeme in &lexemes {
-            job.append(
-                &key[lexeme.slice.clone()],
-                0.0,
-                lexeme.kind.into_text_format(),
-            );
+            let text = &key[lexeme.slice.clone()];
+            let mut format = lexeme.kind.text_format_for(text, theme);
+
+            // the least used registers keep a third of their color
+            if let Some(count) = lexeme_register(text)
+                .filter(|_| lexeme.kind == LexemeKind::Reg)
+                .and_then(|index| counts.get(&index))
+            {
+                let heat = *count as f32 / max_count as f32;
+                format.color = format.color.gamma_multiply(0.35 + 0.65 * heat);
+            }
+
+            if options.show_whitespace {
+                let at_end = lexeme.slice.end == key.len();
+                append_marking_whitespace(&mut job, text, format, lexeme.kind, at_end, theme);
+            } else {
+                job.append(text, 0.0, format);
+            }
         }
 
         (job, Lexer::lexemes_into_btree(lexemes))
@@ -50,7 +251,21 @@ impl ComputerMut<&str, HighlightingCtx> for Highlighting {
 }
 
 /// Highlight a bit of text. Memoized, so multiple calls in a frame will not
-/// compute anything new.
-pub fn highlight(ctx: &egui::Context, text: &str) -> HighlightingCtx {
-    ctx.memory_mut(|m| m.caches.cache::<HighlightingCache>().get(text))
+/// compute anything new. `version` must change whenever the colors of
+/// `theme` do.
+pub fn highlight(
+    ctx: &egui::Context,
+    text: &str,
+    theme: &HighlightTheme,
+    version: u64,
+    options: HighlightOptions,
+) -> HighlightingCtx {
+    let key = HighlightingKey {
+        text,
+        theme,
+        version,
+        options,
+    };
+
+    ctx.memory_mut(|m| m.caches.cache::<HighlightingCache>().get(key))
 }
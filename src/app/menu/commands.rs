@@ -1,13 +1,26 @@
 use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
 
 use egui::{Key, KeyboardShortcut, Modifiers};
 use lazy_static::lazy_static;
 
 use crate::{
-    app::App,
-    simulator::{ProcMessage, ADDR_STATIC},
+    app::{
+        menu::{goto_line::GotoLine, palette::Palette},
+        settings::Theme,
+        App, Baseline,
+    },
+    assembler::{assemble, format::format_source, AssembledProgram},
+    simulator::{Memory, ProcMessage, Registers, ADDR_STATIC},
 };
 
+/// The maximum number of changed memory ranges logged by "Compare to Baseline".
+const BASELINE_RANGES_SHOWN: usize = 16;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Category {
     File,
@@ -89,24 +102,37 @@ commands! {
             ctx.app.set_file(None, ctx.frame);
         },
 
+    File / "New Editor Tab" (CTRL, SHIFT + N) => command_new_editor
+        fn command_new_editor(ctx: CommandCtx<'_>) {
+            ctx.app.open_editor();
+        },
+
     File / "Open File" (CTRL + O) => command_open_file
         fn command_open_file(ctx: CommandCtx<'_>) {
+            #[cfg(not(target_arch = "wasm32"))]
             if let Some(path) = rfd::FileDialog::new()
                 .add_filter("MIPS Assembly Files", &["s"])
                 .pick_file()
             {
                 ctx.app.load_file(path, ctx.frame).expect("failed to load file");
             }
+
+            #[cfg(target_arch = "wasm32")]
+            ctx.app.pick_file(ctx.ctx);
         },
 
     File / "Save File" (CTRL + S) => command_save_file
         fn command_save_file(ctx: CommandCtx<'_>) {
+            #[cfg(not(target_arch = "wasm32"))]
             if let Some(path) = rfd::FileDialog::new()
                 .add_filter("MIPS Assembly Files", &["s"])
                 .pick_file()
             {
                 ctx.app.load_file(path, ctx.frame).expect("failed to save file");
             }
+
+            #[cfg(target_arch = "wasm32")]
+            ctx.app.log("Saving files is not available in the browser");
         },
 
     File / "Save File As" (CTRL, SHIFT + S) => command_save_file_as
@@ -114,23 +140,275 @@ commands! {
             ctx.app.save_file(true, ctx.frame).expect("failed to save file");
         },
 
+    Edit / "Format Source" (CTRL, SHIFT + F) => command_format_source
+        fn command_format_source(ctx: CommandCtx<'_>) {
+            let editor = ctx.app.editor_mut();
+            let formatted = format_source(&editor.body);
+            if formatted != editor.body {
+                editor.body = formatted;
+                editor.unsaved = true;
+            }
+        },
+
+    Edit / "Go to Line" (CTRL + G) => command_goto_line
+        fn command_goto_line(ctx: CommandCtx<'_>) {
+            ctx.app.goto_line = Some(GotoLine::default());
+        },
+
+    File / "Save Memory Snapshot" (+ None) => command_save_memory_snapshot
+        fn command_save_memory_snapshot(ctx: CommandCtx<'_>) {
+            #[cfg(target_arch = "wasm32")]
+            ctx.app.log("Memory snapshots are not available in the browser");
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Memory Snapshots", &["mem"])
+                .save_file()
+            {
+                let result = File::create(&path)
+                    .and_then(|file| ctx.app.proc.mem.read().write_snapshot(BufWriter::new(file)));
+
+                match result {
+                    Ok(()) => ctx.app.log(format!("Saved memory snapshot to {}", path.display())),
+                    Err(e) => ctx.app.log(format!("Failed to save memory snapshot: {e}")),
+                }
+            }
+        },
+
+    File / "Load Memory Snapshot" (+ None) => command_load_memory_snapshot
+        fn command_load_memory_snapshot(ctx: CommandCtx<'_>) {
+            #[cfg(target_arch = "wasm32")]
+            ctx.app.log("Memory snapshots are not available in the browser");
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Memory Snapshots", &["mem"])
+                .pick_file()
+            {
+                match File::open(&path).and_then(|file| Memory::read_snapshot(BufReader::new(file))) {
+                    Ok(blocks) => {
+                        ctx.app.proc.mem.write().restore(blocks);
+                        ctx.app.memory.request_refresh();
+                        ctx.app.log(format!("Loaded memory snapshot from {}", path.display()));
+                    }
+                    Err(e) => ctx.app.log(format!("Failed to load memory snapshot: {e}")),
+                }
+            }
+        },
+
+    View / "Command Palette" (CTRL + P) => command_palette
+        fn command_palette(ctx: CommandCtx<'_>) {
+            ctx.app.palette = Some(Palette::default());
+        },
+
+    View / "Settings" (+ None) => command_settings
+        fn command_settings(ctx: CommandCtx<'_>) {
+            ctx.app.settings_open = true;
+        },
+
+    View / "Toggle Theme" (+ None) => command_toggle_theme
+        fn command_toggle_theme(ctx: CommandCtx<'_>) {
+            let theme = match ctx.app.settings.theme {
+                Theme::Dark => Theme::Light,
+                Theme::Light => Theme::Dark,
+            };
+
+            ctx.app.settings.theme = theme;
+            ctx.app.settings.highlight_version += 1;
+            ctx.ctx.set_visuals(theme.visuals());
+            ctx.app.save_settings();
+        },
+
+    View / "Toggle Register Heatmap" (+ None) => command_toggle_register_heatmap
+        fn command_toggle_register_heatmap(ctx: CommandCtx<'_>) {
+            ctx.app.settings.register_heatmap = !ctx.app.settings.register_heatmap;
+            ctx.app.save_settings();
+        },
+
+    View / "Clear Log" (+ None) => command_clear_log
+        fn command_clear_log(ctx: CommandCtx<'_>) {
+            ctx.app.output.log.clear();
+        },
+
+    View / "Clear Output" (+ None) => command_clear_output
+        fn command_clear_output(ctx: CommandCtx<'_>) {
+            ctx.app.output.io.reset();
+        },
+
     Run / "Assemble" (+ None) => command_assemble
         fn command_assemble(ctx: CommandCtx<'_>) {
-            ctx.app.proc_tx.send(ProcMessage::Load(ctx.app.body.clone())).unwrap();
+            if let Some(program) = assemble_body(ctx.app) {
+                ctx.app.proc_tx.send(ProcMessage::Load(program)).unwrap();
+            }
+        },
+
+    Run / "Reload Code Only" (+ None) => command_reload_code
+        fn command_reload_code(ctx: CommandCtx<'_>) {
+            if let Some(program) = assemble_body(ctx.app) {
+                ctx.app.proc_tx.send(ProcMessage::ReloadCode(program)).unwrap();
+            }
         },
 
     Run / "Reset" (CTRL, SHIFT + R) => command_reset
         fn command_reset(ctx: CommandCtx<'_>) {
             ctx.app.proc.pc_lines = None;
-            ctx.app.output.io.reset();
-            ctx.app.memory.offset = ADDR_STATIC;
+            ctx.app.proc.line_pcs = None;
+            ctx.app.proc.labels = None;
+            reset_io(ctx.app);
+            ctx.app.memory.set_offset(ADDR_STATIC);
             ctx.app.proc_tx.send(ProcMessage::Reset).unwrap();
         },
 
+    Run / "Restart" (CTRL, ALT + R) => command_restart
+        fn command_restart(ctx: CommandCtx<'_>) {
+            reset_io(ctx.app);
+            ctx.app.proc_tx.send(ProcMessage::Restart).unwrap();
+        },
+
     Run / "Step" (CTRL + Space) => command_step
         fn command_step(ctx: CommandCtx<'_>) {
-            ctx.app.proc_tx.send(ProcMessage::Step).unwrap();
+            let message = if ctx.app.step_lines {
+                ProcMessage::StepLine
+            } else {
+                ProcMessage::Step
+            };
+            ctx.app.proc_tx.send(message).unwrap();
+        },
+
+    Run / "Step N" (CTRL, ALT + Space) => command_step_many
+        fn command_step_many(ctx: CommandCtx<'_>) {
+            ctx.app.proc_tx.send(ProcMessage::StepMany(ctx.app.step_count)).unwrap();
+        },
+
+    Run / "Step Over" (CTRL, SHIFT + Space) => command_step_over
+        fn command_step_over(ctx: CommandCtx<'_>) {
+            ctx.app.proc_tx.send(ProcMessage::StepOver).unwrap();
+        },
+
+    Run / "Run" (CTRL + R) => command_run
+        fn command_run(ctx: CommandCtx<'_>) {
+            ctx.app.proc_tx.send(ProcMessage::Run).unwrap();
+        },
+
+    Run / "Stop" (+ None) => command_stop
+        fn command_stop(ctx: CommandCtx<'_>) {
+            ctx.app.proc_tx.send(ProcMessage::Stop).unwrap();
+        },
+
+    Run / "Save Trace" (+ None) => command_save_trace
+        fn command_save_trace(ctx: CommandCtx<'_>) {
+            #[cfg(target_arch = "wasm32")]
+            ctx.app.log("Saving traces is not available in the browser");
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Trace Logs", &["txt"])
+                .save_file()
+            {
+                ctx.app.proc_tx.send(ProcMessage::SaveTrace(path)).unwrap();
+            }
         },
+
+    Run / "Take Baseline" (+ None) => command_take_baseline
+        fn command_take_baseline(ctx: CommandCtx<'_>) {
+            let mut mem = Memory::new();
+            mem.restore(ctx.app.proc.mem.read().snapshot());
+
+            ctx.app.proc.baseline = Some(Baseline {
+                regs: ctx.app.proc.regs,
+                mem,
+            });
+            ctx.app.log("Took a baseline of the registers and memory");
+        },
+
+    Run / "Compare to Baseline" (+ None) => command_compare_baseline
+        fn command_compare_baseline(ctx: CommandCtx<'_>) {
+            let Some(baseline) = &ctx.app.proc.baseline else {
+                ctx.app.log("No baseline to compare to, take one first");
+                return;
+            };
+
+            let regs = (0..32)
+                .filter(|i| ctx.app.proc.regs[*i] != baseline.regs[*i])
+                .collect::<Vec<_>>();
+            let bytes = ctx.app.proc.mem.read().diff(&baseline.mem);
+
+            // group the changed bytes into contiguous ranges
+            let mut ranges: Vec<(usize, usize)> = vec![];
+            for addr in bytes.iter().copied() {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end == addr => *end += 1,
+                    _ => ranges.push((addr, addr + 1)),
+                }
+            }
+
+            let mut message = format!(
+                "Compared to baseline: {} registers and {} bytes differ",
+                regs.len(),
+                bytes.len(),
+            );
+            for i in regs {
+                message += &format!(
+                    "\n  ${}: 0x{:08x} -> 0x{:08x}",
+                    Registers::name(i),
+                    baseline.regs[i].to_u32(),
+                    ctx.app.proc.regs[i].to_u32(),
+                );
+            }
+            for (start, end) in ranges.iter().take(BASELINE_RANGES_SHOWN) {
+                message += &format!("\n  0x{start:08x}..0x{end:08x} ({} bytes)", end - start);
+            }
+            if ranges.len() > BASELINE_RANGES_SHOWN {
+                message += &format!("\n  ...and {} more ranges", ranges.len() - BASELINE_RANGES_SHOWN);
+            }
+
+            ctx.app.log(message);
+        },
+
+    Run / "Clear Baseline" (+ None) => command_clear_baseline
+        fn command_clear_baseline(ctx: CommandCtx<'_>) {
+            ctx.app.proc.baseline = None;
+        },
+}
+
+/// Clear the program I/O for a new run, or mark where it begins if the
+/// settings keep it.
+fn reset_io(app: &mut App) {
+    if app.settings.clear_io_on_reset {
+        app.output.io.reset();
+    } else {
+        app.output.io.separate();
+    }
+}
+
+/// Assemble the editor body, logging any warnings, expansions or errors, and
+/// update the line and label maps of the app.
+pub fn assemble_body(app: &mut App) -> Option<AssembledProgram> {
+    match assemble(&app.editor().body, &app.settings.assemble) {
+        Ok(program) => {
+            for warning in program.warnings.iter() {
+                app.log(format!("Warning: {warning}"));
+            }
+
+            if app.settings.log_expansions && !program.expansions.is_empty() {
+                let mut message = "Expanded instructions:".to_owned();
+                for expansion in program.expansions.iter() {
+                    message += &format!("\n  line {}: {expansion}", expansion.line + 1);
+                }
+                app.log(message);
+            }
+
+            app.proc.pc_lines = Some(program.addr_lines.clone());
+            app.proc.line_pcs = Some(program.line_addrs.clone());
+            app.proc.labels = Some(program.labels.clone());
+            app.assembled_editor = Some(app.active_editor);
+            Some(program)
+        }
+        Err(e) => {
+            app.log(format!("Assemble error: {e}"));
+            None
+        }
+    }
 }
 
 lazy_static! {
@@ -0,0 +1,64 @@
+use egui::{Key, Modifiers};
+
+use crate::app::App;
+
+/// The state of the go to line prompt, while it is open.
+#[derive(Debug, Default)]
+pub struct GotoLine {
+    pub input: String,
+    pub error: bool,
+}
+
+/// Show the go to line prompt if it is open, moving the active editor to the
+/// entered line.
+pub fn show_goto_line(app: &mut App, ctx: &egui::Context) {
+    let Some(goto) = &mut app.goto_line else {
+        return;
+    };
+
+    let (enter, escape) = ctx.input_mut(|i| {
+        (
+            i.consume_key(Modifiers::NONE, Key::Enter),
+            i.consume_key(Modifiers::NONE, Key::Escape),
+        )
+    });
+
+    let lines = app.editors[app.active_editor].body.lines().count().max(1);
+    let mut chosen = None;
+
+    egui::Window::new("Go to Line")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .show(ctx, |ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut goto.input)
+                    .hint_text(format!("Line number (1-{lines})"))
+                    .desired_width(200.0),
+            );
+            input.request_focus();
+            if input.changed() {
+                goto.error = false;
+            }
+
+            if goto.error {
+                ui.colored_label(ui.visuals().error_fg_color, "Not a line number");
+            }
+
+            if enter {
+                match goto.input.trim().parse::<usize>() {
+                    Ok(line) if line > 0 => chosen = Some(line),
+                    _ => goto.error = true,
+                }
+            }
+        });
+
+    if escape || chosen.is_some() {
+        app.goto_line = None;
+    }
+
+    if let Some(line) = chosen {
+        app.editor_mut().goto_line = Some(line - 1);
+    }
+}
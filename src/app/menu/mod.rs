@@ -1,6 +1,8 @@
 pub mod commands;
+pub mod goto_line;
+pub mod palette;
 
-use crate::AppContainer;
+use crate::{simulator::ProcMessage, AppContainer};
 
 use self::commands::{CommandCtx, CATEGORIES, COMMANDS, COMMAND_CATEGORIES};
 
@@ -24,9 +26,27 @@ pub fn show_menu_bar(container: &mut AppContainer, ctx: &egui::Context, frame: &
                     }
                 });
             }
+
+            ui.separator();
+
+            ui.toggle_value(&mut app.step_lines, "Line step")
+                .on_hover_text(
+                    "Step a source line at a time, running pseudo instructions as a whole.",
+                );
+
+            ui.add(egui::DragValue::new(&mut app.step_count).clamp_range(1..=1_000_000));
+            if ui.button("Step N").clicked() {
+                app.proc_tx
+                    .send(ProcMessage::StepMany(app.step_count))
+                    .unwrap();
+            }
         });
     });
 
+    // while typing, only shortcuts with Ctrl or Alt run, so that plain keys
+    // always reach the text field
+    let typing = ctx.wants_keyboard_input();
+
     ctx.input_mut(|i| {
         if i.keys_down.is_empty() {
             return;
@@ -35,7 +55,13 @@ pub fn show_menu_bar(container: &mut AppContainer, ctx: &egui::Context, frame: &
         // TODO: ideally we use a hash map to do this, but egui doesn't
         // TODO: make hashing key shortcuts very easy
         for command in COMMANDS.iter().filter(|c| c.keybind.is_some()) {
-            if i.consume_shortcut(command.keybind.as_ref().unwrap()) {
+            let shortcut = command.keybind.as_ref().unwrap();
+            let modifiers = shortcut.modifiers;
+            if typing && !(modifiers.ctrl || modifiers.command || modifiers.alt) {
+                continue;
+            }
+
+            if i.consume_shortcut(shortcut) {
                 (command.action)(CommandCtx { app, ctx, frame });
                 break;
             }
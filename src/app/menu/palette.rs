@@ -0,0 +1,135 @@
+use egui::{Key, Modifiers};
+
+use crate::app::App;
+
+use super::commands::{Command, CommandCtx, COMMANDS};
+
+/// The most matching commands listed at once.
+const PALETTE_MAX_SHOWN: usize = 12;
+
+/// The state of the command palette, while it is open.
+#[derive(Debug, Default)]
+pub struct Palette {
+    pub query: String,
+    pub selected: usize,
+}
+
+/// Score how well `query` matches `text`, ignoring case and whitespace in the
+/// query. Every character of the query must appear in `text` in order;
+/// consecutive characters and characters at the start of words score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last = None;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = pos + text[pos..].iter().position(|t| *t == c)?;
+
+        score += 1;
+        if last.is_some_and(|last| found == last + 1) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+
+        last = Some(found);
+        pos = found + 1;
+    }
+
+    Some(score)
+}
+
+/// The commands matching `query`, best first. An empty query lists every
+/// command.
+fn matches(query: &str) -> Vec<&'static Command> {
+    let mut scored = COMMANDS
+        .iter()
+        .filter_map(|command| {
+            let text = format!("{}: {}", command.category.name(), command.name);
+            fuzzy_score(query, &text).map(|score| (score, command))
+        })
+        .collect::<Vec<_>>();
+
+    // stable, so equal scores keep the menu order
+    scored.sort_by_key(|(score, _)| -score);
+    scored.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Show the command palette if it is open, running the chosen command.
+pub fn show_palette(app: &mut App, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    let Some(palette) = &mut app.palette else {
+        return;
+    };
+
+    let (up, down, enter, escape) = ctx.input_mut(|i| {
+        (
+            i.consume_key(Modifiers::NONE, Key::ArrowUp),
+            i.consume_key(Modifiers::NONE, Key::ArrowDown),
+            i.consume_key(Modifiers::NONE, Key::Enter),
+            i.consume_key(Modifiers::NONE, Key::Escape),
+        )
+    });
+
+    let mut chosen = None;
+    let mut close = escape;
+
+    egui::Window::new("Command Palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .show(ctx, |ui| {
+            let query = ui.add(
+                egui::TextEdit::singleline(&mut palette.query)
+                    .hint_text("Search commands...")
+                    .desired_width(400.0),
+            );
+            query.request_focus();
+            if query.changed() {
+                palette.selected = 0;
+            }
+
+            let matches = matches(&palette.query);
+            let shown = matches.len().min(PALETTE_MAX_SHOWN);
+            if shown == 0 {
+                ui.weak("No matching commands");
+                return;
+            }
+
+            if up {
+                palette.selected = palette.selected.checked_sub(1).unwrap_or(shown - 1);
+            }
+            if down {
+                palette.selected = (palette.selected + 1) % shown;
+            }
+            palette.selected = palette.selected.min(shown - 1);
+
+            for (i, command) in matches.iter().take(shown).enumerate() {
+                let mut text = format!("{}: {}", command.category.name(), command.name);
+                if let Some(shortcut) = &command.keybind {
+                    text += &format!("  ({})", ui.ctx().format_shortcut(shortcut));
+                }
+
+                if ui.selectable_label(i == palette.selected, text).clicked() {
+                    chosen = Some(*command);
+                }
+            }
+
+            if enter {
+                chosen = Some(matches[palette.selected]);
+            }
+        });
+
+    if chosen.is_some() {
+        close = true;
+    }
+    if close {
+        app.palette = None;
+    }
+
+    if let Some(command) = chosen {
+        (command.action)(CommandCtx { app, ctx, frame });
+    }
+}
@@ -1,26 +1,81 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use instant::Instant;
 use parking_lot::RwLock;
 
-use crate::simulator::{AppMessage, AppRx, Memory, ProcSync, ProcTx, RegSync, Register};
+use crate::simulator::{
+    AppMessage, AppRx, Cp0, Memory, ProcMessage, ProcSync, ProcTx, RegSync, Register,
+};
 
-use self::tabs::{memory::MemoryViewer, output::Output};
+use self::{
+    menu::{goto_line::GotoLine, palette::Palette},
+    settings::{AutoAssemble, Settings},
+    tabs::{console::Console, editor::EditorBuffer, memory::MemoryViewer, output::Output, AppTab},
+};
 
 pub mod highlighting;
 pub mod menu;
+pub mod settings;
+pub mod status;
 pub mod tabs;
 
+/// How long the editor must be idle before assembling automatically after
+/// edits.
+const AUTO_ASSEMBLE_DELAY: Duration = Duration::from_millis(750);
+
+/// How often the files of the editors are checked for changes on disk.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The modified time of a file, if it can be read.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 #[derive(Debug)]
 pub struct App {
-    // editor
-    pub body: String,
+    // editors, with the one that last had focus feeding Assemble
+    pub editors: Vec<EditorBuffer>,
+    pub active_editor: usize,
+    // the editor the loaded program was assembled from
+    pub assembled_editor: Option<usize>,
+    // when a body last changed, while waiting to auto-assemble
+    pub edited_at: Option<Instant>,
+    // when the editors' files were last checked for changes on disk
+    pub disk_checked_at: Instant,
+    // tabs to add to the dock, e.g. new editors
+    pub new_tabs: Vec<AppTab>,
+    // files picked in the browser, as name and contents, waiting to be opened
+    #[cfg(target_arch = "wasm32")]
+    pub picked_files: crate::util::ParBuf<(String, String)>,
+
     pub output: Output,
-    pub file: Option<PathBuf>,
-    pub unsaved: bool,
 
     // memory
     pub memory: MemoryViewer,
 
+    // console
+    pub console: Console,
+
+    // settings
+    pub settings: Settings,
+    pub settings_open: bool,
+
+    // the command palette, while it is open
+    pub palette: Option<Palette>,
+    // the go to line prompt, while it is open
+    pub goto_line: Option<GotoLine>,
+
+    // the number of instructions to run with "Step N"
+    pub step_count: usize,
+
+    // whether "Step" steps a source line at a time instead of an instruction
+    pub step_lines: bool,
+
     // processor synchronization
     pub proc: ProcState,
     pub proc_tx: ProcTx,
@@ -30,20 +85,50 @@ pub struct App {
 #[derive(Debug)]
 pub struct ProcState {
     pub regs: [Register; 32],
+    pub hi: u32,
+    pub lo: u32,
+    pub cp0: Cp0,
     pub mem: Arc<RwLock<Memory>>,
     pub pc: usize,
     pub pc_lines: Option<HashMap<usize, u32>>,
+    pub line_pcs: Option<HashMap<u32, usize>>,
+    pub labels: Option<HashMap<String, usize>>,
+    pub loaded: bool,
     pub active: bool,
+    pub running: bool,
+    pub inst_count: u64,
+    pub coverage: HashSet<usize>,
+    pub watchpoints: BTreeSet<usize>,
+    pub baseline: Option<Baseline>,
+}
+
+/// The color of values that differ from the baseline.
+pub const DIFF_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 140, 60);
+
+/// A snapshot of the processor state, to compare the current state against.
+#[derive(Debug)]
+pub struct Baseline {
+    pub regs: [Register; 32],
+    pub mem: Memory,
 }
 
 impl ProcState {
     fn sync(&mut self, sync: ProcSync) {
         self.pc = sync.pc;
+        self.hi = sync.hi;
+        self.lo = sync.lo;
+        self.cp0 = sync.cp0;
+        self.loaded = sync.loaded;
         self.active = sync.active;
+        self.running = sync.running;
+        self.inst_count = sync.inst_count;
 
         match sync.regs {
             RegSync::Set(regs) => {
                 self.regs = regs;
+
+                // a hard sync sends all of the executed addresses
+                self.coverage.clear();
             }
             RegSync::Diff(diff) => {
                 for (index, value) in diff.into_iter() {
@@ -51,25 +136,63 @@ impl ProcState {
                 }
             }
         }
+
+        self.coverage.extend(sync.executed);
     }
 }
 
 impl App {
     pub fn new(proc_tx: ProcTx, app_rx: AppRx, mem: Arc<RwLock<Memory>>) -> Self {
+        let settings = Settings::load();
+
+        let mut output = Output::default();
+        output.log.set_limit(settings.log_limit);
+
+        proc_tx
+            .send(ProcMessage::Settings(settings.proc.clone()))
+            .unwrap();
+
         Self {
-            body: String::new(),
-            output: Output::default(),
-            file: None,
-            unsaved: false,
+            editors: vec![EditorBuffer::default()],
+            active_editor: 0,
+            assembled_editor: None,
+            edited_at: None,
+            disk_checked_at: Instant::now(),
+            new_tabs: vec![],
+            #[cfg(target_arch = "wasm32")]
+            picked_files: Default::default(),
+
+            output,
 
             memory: MemoryViewer::default(),
 
+            console: Console::default(),
+
+            settings,
+            settings_open: false,
+            palette: None,
+            goto_line: None,
+
+            step_count: 1,
+            step_lines: false,
+
             proc: ProcState {
                 regs: [Register(0); 32],
+                hi: 0,
+                lo: 0,
+                cp0: Cp0::default(),
                 mem,
                 pc: 0,
                 pc_lines: None,
+                line_pcs: None,
+                labels: None,
+                loaded: false,
                 active: false,
+                running: false,
+                inst_count: 0,
+                coverage: HashSet::new(),
+                watchpoints: BTreeSet::new(),
+                baseline: None,
             },
             proc_tx,
             app_rx,
@@ -84,72 +207,237 @@ impl App {
             .expect("failed to log message");
     }
 
+    /// Save the settings, logging any failure.
+    fn save_settings(&self) {
+        if let Err(e) = self.settings.save() {
+            self.log(format!("Failed to save settings: {e}"));
+        }
+    }
+
+    /// The editor that last had focus.
+    pub fn editor(&self) -> &EditorBuffer {
+        &self.editors[self.active_editor]
+    }
+
+    /// The editor that last had focus, mutably.
+    pub fn editor_mut(&mut self) -> &mut EditorBuffer {
+        &mut self.editors[self.active_editor]
+    }
+
+    /// Open an empty editor in a new tab, and make it the active one.
+    fn open_editor(&mut self) {
+        self.editors.push(EditorBuffer::default());
+        self.active_editor = self.editors.len() - 1;
+        self.new_tabs.push(AppTab::Editor(self.active_editor));
+    }
+
+    // the browser has no window title to set
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
     fn set_file(&mut self, path: Option<PathBuf>, frame: &mut eframe::Frame) {
         match path {
             Some(path) => {
+                #[cfg(not(target_arch = "wasm32"))]
                 if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
                     frame.set_window_title(&format!("mipsim - {file_name}"));
                 }
-                self.file = Some(path);
+                self.editor_mut().file = Some(path);
             }
             None => {
+                #[cfg(not(target_arch = "wasm32"))]
                 frame.set_window_title("mipsim");
-                self.file = None;
+                self.editor_mut().file = None;
             }
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     fn load_file(&mut self, path: PathBuf, frame: &mut eframe::Frame) -> std::io::Result<()> {
-        self.body = std::fs::read_to_string(&path)?;
+        let editor = self.editor_mut();
+        editor.body = std::fs::read_to_string(&path)?;
+        editor.disk_modified = modified_time(&path);
+        editor.changed_on_disk = false;
         self.set_file(Some(path), frame);
         self.log("Loaded file");
         Ok(())
     }
 
+    /// Pick a file with the browser's file dialog, opening it in the active
+    /// editor once it has been read.
+    #[cfg(target_arch = "wasm32")]
+    fn pick_file(&self, ctx: &egui::Context) {
+        let tx = self.picked_files.tx.clone();
+        let ctx = ctx.clone();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("MIPS Assembly Files", &["s"])
+                .pick_file()
+                .await
+            else {
+                return;
+            };
+
+            let body = String::from_utf8_lossy(&file.read().await).into_owned();
+            if tx.send((file.file_name(), body)).is_ok() {
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
     fn save_file(&mut self, save_as: bool, frame: &mut eframe::Frame) -> std::io::Result<()> {
-        if !self.unsaved {
+        if !self.editor().unsaved {
             return Ok(());
         }
 
-        match &self.file {
-            Some(file) if !save_as => std::fs::write(file, &self.body)?,
+        match &self.editor().file {
+            Some(file) if !save_as => std::fs::write(file, &self.editor().body)?,
+            #[cfg(target_arch = "wasm32")]
+            _ => {
+                self.log("Saving files is not available in the browser");
+                return Ok(());
+            }
+            #[cfg(not(target_arch = "wasm32"))]
             _ => {
                 if let Some(path) = rfd::FileDialog::new()
                     .add_filter("MIPS Assembly Files", &["s"])
                     .save_file()
                 {
-                    std::fs::write(&path, &self.body)?;
+                    std::fs::write(&path, &self.editor().body)?;
                     self.set_file(Some(path), frame);
                 }
             }
         }
 
-        self.unsaved = false;
+        let editor = self.editor_mut();
+        editor.unsaved = false;
+        editor.disk_modified = editor.file.as_deref().and_then(modified_time);
+        editor.changed_on_disk = false;
         self.output.log.tx.send("File saved".into()).unwrap();
 
+        if self.settings.auto_assemble == AutoAssemble::OnSave {
+            self.auto_assemble();
+        }
+
         Ok(())
     }
 
-    pub fn update(&mut self, _ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    /// Replace the active editor's body with its file on disk, discarding any
+    /// unsaved changes.
+    pub fn reload_file(&mut self) {
+        let editor = self.editor_mut();
+        let Some(path) = editor.file.clone() else {
+            return;
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(body) => {
+                editor.body = body;
+                editor.unsaved = false;
+                editor.disk_modified = modified_time(&path);
+                editor.changed_on_disk = false;
+                self.log(format!("Reloaded {} from disk", path.display()));
+            }
+            Err(e) => self.log(format!("Failed to reload file: {e}")),
+        }
+    }
+
+    /// Keep the active editor's body as it is after its file changed on
+    /// disk, until the file changes again.
+    pub fn ignore_disk_change(&mut self) {
+        let editor = self.editor_mut();
+        editor.disk_modified = editor.file.as_deref().and_then(modified_time);
+        editor.changed_on_disk = false;
+    }
+
+    /// Flag the editors whose files were modified on disk since they were
+    /// loaded or saved.
+    fn check_disk_changes(&mut self) {
+        for editor in self.editors.iter_mut() {
+            let Some(file) = &editor.file else {
+                continue;
+            };
+
+            let modified = modified_time(file);
+            if modified.is_some() && modified != editor.disk_modified {
+                editor.changed_on_disk = true;
+            }
+        }
+    }
+
+    /// Assemble and load the editor body, unless the program is running.
+    /// Errors are only logged.
+    fn auto_assemble(&mut self) {
+        if self.proc.running {
+            return;
+        }
+
+        if let Some(program) = menu::commands::assemble_body(self) {
+            self.proc_tx.send(ProcMessage::Load(program)).unwrap();
+        }
+    }
+
+    pub fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         while let Ok(message) = self.app_rx.try_recv() {
             match message {
                 AppMessage::Sync(sync) => {
                     self.proc.sync(sync);
                     self.memory.request_refresh();
                 }
-                AppMessage::PcLines(map) => {
-                    self.proc.pc_lines = Some(map);
-                }
                 AppMessage::Io(string) => {
                     self.output.io.add(string);
                 }
                 AppMessage::Log(string) => {
                     self.output.log.tx.send(string).unwrap();
                 }
+                AppMessage::AwaitingChar(awaiting) => {
+                    self.output.io.awaiting_char = awaiting;
+                }
+                AppMessage::Console(result) => {
+                    self.console.output.push(result);
+                }
             }
         }
 
         // update output buffers
         self.output.log.update();
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.picked_files.update();
+            while let Some((name, body)) = self.picked_files.pop_front() {
+                let editor = self.editor_mut();
+                editor.body = body;
+                editor.unsaved = false;
+                self.log(format!("Opened {name}"));
+            }
+        }
+
+        // assemble once the editor has been idle for a moment
+        if let Some(edited_at) = self.edited_at {
+            let elapsed = edited_at.elapsed();
+            if self.settings.auto_assemble != AutoAssemble::OnEdit {
+                self.edited_at = None;
+            } else if elapsed >= AUTO_ASSEMBLE_DELAY {
+                self.edited_at = None;
+                self.auto_assemble();
+            } else {
+                ctx.request_repaint_after(AUTO_ASSEMBLE_DELAY - elapsed);
+            }
+        }
+
+        // poll the files of the editors for changes made by other programs
+        if self.editors.iter().any(|editor| editor.file.is_some()) {
+            if self.disk_checked_at.elapsed() >= DISK_CHECK_INTERVAL {
+                self.disk_checked_at = Instant::now();
+                self.check_disk_changes();
+            }
+            ctx.request_repaint_after(DISK_CHECK_INTERVAL);
+        }
+
+        // keep receiving syncs while the processor runs on its own
+        if self.proc.running {
+            ctx.request_repaint();
+        }
     }
 }
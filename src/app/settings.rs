@@ -0,0 +1,804 @@
+use std::{fs, io, ops::RangeInclusive, path::PathBuf};
+
+use egui::Color32;
+
+use crate::{
+    assembler::AssembleOptions,
+    simulator::{ClockMode, ProcMessage, ProcSettings, Registers, REG_A0},
+};
+
+use super::{
+    highlighting::{HighlightOptions, HighlightTheme},
+    App,
+};
+
+/// The default maximum number of lines kept in the log.
+pub const DEFAULT_LOG_LIMIT: usize = 1000;
+
+/// The range of editor indent widths, in spaces.
+const INDENT_WIDTHS: RangeInclusive<usize> = 1..=16;
+
+/// The color theme of the app.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// The egui visuals for this theme.
+    pub fn visuals(&self) -> egui::Visuals {
+        match self {
+            Self::Dark => egui::Visuals::dark(),
+            Self::Light => egui::Visuals::light(),
+        }
+    }
+
+    /// The default syntax highlighting colors for this theme.
+    pub fn default_highlight(&self) -> HighlightTheme {
+        match self {
+            Self::Dark => HighlightTheme::DARK,
+            Self::Light => HighlightTheme::LIGHT,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        }
+    }
+}
+
+/// When the editor body is assembled and loaded automatically.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AutoAssemble {
+    #[default]
+    Off,
+
+    /// Assemble whenever the file is saved.
+    OnSave,
+
+    /// Assemble shortly after the editor body stops changing.
+    OnEdit,
+}
+
+impl AutoAssemble {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::OnSave => "save",
+            Self::OnEdit => "edit",
+        }
+    }
+}
+
+/// User settings for the app, persisted to the config directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Settings forwarded to the processor.
+    pub proc: ProcSettings,
+
+    /// Options used when assembling.
+    pub assemble: AssembleOptions,
+
+    /// When to assemble and load the editor body without being asked.
+    pub auto_assemble: AutoAssemble,
+
+    /// Whether to log what pseudo instructions expanded into when assembling.
+    pub log_expansions: bool,
+
+    /// The maximum number of lines kept in the log.
+    pub log_limit: usize,
+
+    /// Whether resetting or restarting the processor clears the program I/O.
+    /// Otherwise, a separator line marks where the new run begins.
+    pub clear_io_on_reset: bool,
+
+    /// The color theme.
+    pub theme: Theme,
+
+    /// Whether the editor scrolls to the PC line when the PC changes.
+    pub auto_scroll: bool,
+
+    /// Whether the editor shows the address of each instruction line in a
+    /// margin, once assembled.
+    pub show_addresses: bool,
+
+    /// Whether the editor marks tabs and trailing whitespace.
+    pub show_whitespace: bool,
+
+    /// Whether the editor fades registers by how often they are referenced.
+    pub register_heatmap: bool,
+
+    /// The number of spaces Tab inserts in the editor.
+    pub indent_width: usize,
+
+    /// Whether Tab inserts a literal tab rather than spaces.
+    pub indent_tabs: bool,
+
+    /// Whether the registers tab shows each register's number next to its
+    /// name, e.g. `$t0 ($8)`.
+    pub show_register_numbers: bool,
+
+    /// How often the memory viewer re-reads memory while the processor is
+    /// active, in milliseconds. Zero re-reads it every frame.
+    pub memory_refresh_ms: u64,
+
+    /// The syntax highlighting colors of the dark theme.
+    pub highlight_dark: HighlightTheme,
+
+    /// The syntax highlighting colors of the light theme.
+    pub highlight_light: HighlightTheme,
+
+    /// Bumped whenever the highlighting colors in use change.
+    /// Not persisted.
+    pub highlight_version: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            proc: ProcSettings::default(),
+            assemble: AssembleOptions::default(),
+            auto_assemble: AutoAssemble::default(),
+            log_expansions: true,
+            log_limit: DEFAULT_LOG_LIMIT,
+            clear_io_on_reset: true,
+            theme: Theme::default(),
+            auto_scroll: true,
+            show_addresses: false,
+            show_whitespace: false,
+            register_heatmap: false,
+            indent_width: 4,
+            indent_tabs: false,
+            show_register_numbers: true,
+            memory_refresh_ms: 0,
+            highlight_dark: HighlightTheme::DARK,
+            highlight_light: HighlightTheme::LIGHT,
+            highlight_version: 0,
+        }
+    }
+}
+
+fn color_to_hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn color_from_hex(hex: &str) -> Option<Color32> {
+    let rgb = u32::from_str_radix(hex.strip_prefix('#')?, 16).ok()?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Some(Color32::from_rgb(r, g, b))
+}
+
+impl Settings {
+    /// The path of the settings file, if there is a config directory.
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("mipsim").join("settings.cfg"))
+    }
+
+    /// Load the settings from the settings file, falling back to the default
+    /// for anything missing or invalid.
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+
+        if let Some(body) = Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            for (key, value) in body.lines().filter_map(|line| line.split_once('=')) {
+                settings.set(key.trim(), value.trim());
+            }
+        }
+
+        settings
+    }
+
+    /// Save the settings to the settings file.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut body = String::new();
+        for (key, value) in self.entries() {
+            body.push_str(&format!("{key} = {value}\n"));
+        }
+
+        fs::write(path, body)
+    }
+
+    /// The key-value pairs written to the settings file.
+    fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = vec![
+            ("theme".to_owned(), self.theme.name().to_owned()),
+            ("log_limit".to_owned(), self.log_limit.to_string()),
+            (
+                "clear_io_on_reset".to_owned(),
+                self.clear_io_on_reset.to_string(),
+            ),
+            ("auto_scroll".to_owned(), self.auto_scroll.to_string()),
+            ("show_addresses".to_owned(), self.show_addresses.to_string()),
+            (
+                "show_whitespace".to_owned(),
+                self.show_whitespace.to_string(),
+            ),
+            (
+                "register_heatmap".to_owned(),
+                self.register_heatmap.to_string(),
+            ),
+            ("indent_width".to_owned(), self.indent_width.to_string()),
+            ("indent_tabs".to_owned(), self.indent_tabs.to_string()),
+            (
+                "show_register_numbers".to_owned(),
+                self.show_register_numbers.to_string(),
+            ),
+            (
+                "memory_refresh_ms".to_owned(),
+                self.memory_refresh_ms.to_string(),
+            ),
+            (
+                "clock".to_owned(),
+                match self.proc.clock {
+                    ClockMode::System => "system".to_owned(),
+                    ClockMode::Fixed(step) => step.to_string(),
+                },
+            ),
+            (
+                "seed".to_owned(),
+                match self.proc.seed {
+                    Some(seed) => seed.to_string(),
+                    None => "none".to_owned(),
+                },
+            ),
+            ("trace".to_owned(), self.proc.trace.to_string()),
+            ("delay_slots".to_owned(), self.proc.delay_slots.to_string()),
+            ("run_batch".to_owned(), self.proc.run_batch.to_string()),
+            ("max_steps".to_owned(), self.proc.max_steps.to_string()),
+            (
+                "initial_sp".to_owned(),
+                format!("0x{:08x}", self.proc.initial_sp),
+            ),
+            (
+                "track_writers".to_owned(),
+                self.proc.track_writers.to_string(),
+            ),
+            ("coverage".to_owned(), self.proc.coverage.to_string()),
+            (
+                "case_insensitive".to_owned(),
+                self.assemble.case_insensitive.to_string(),
+            ),
+            ("log_expansions".to_owned(), self.log_expansions.to_string()),
+            (
+                "auto_assemble".to_owned(),
+                self.auto_assemble.name().to_owned(),
+            ),
+            (
+                "lint_callee_saved".to_owned(),
+                self.assemble.lint_callee_saved.to_string(),
+            ),
+            (
+                "lint_unreachable".to_owned(),
+                self.assemble.lint_unreachable.to_string(),
+            ),
+            (
+                "ktext_addr".to_owned(),
+                format!("0x{:08x}", self.assemble.ktext_addr),
+            ),
+            (
+                "kdata_addr".to_owned(),
+                format!("0x{:08x}", self.assemble.kdata_addr),
+            ),
+        ];
+
+        for (i, value) in self.proc.initial_args.iter().enumerate() {
+            entries.push((format!("initial_a{i}"), format!("0x{value:08x}")));
+        }
+
+        for (theme, highlight) in [
+            (Theme::Dark, &self.highlight_dark),
+            (Theme::Light, &self.highlight_light),
+        ] {
+            for (kind, color) in highlight.colors() {
+                entries.push((
+                    format!("highlight.{}.{kind}", theme.name()),
+                    color_to_hex(color),
+                ));
+            }
+        }
+
+        entries
+    }
+
+    /// Set a setting from a key-value pair in the settings file. Unknown keys
+    /// and invalid values are ignored.
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "theme" => match value {
+                "dark" => self.theme = Theme::Dark,
+                "light" => self.theme = Theme::Light,
+                _ => (),
+            },
+            "auto_assemble" => match value {
+                "off" => self.auto_assemble = AutoAssemble::Off,
+                "save" => self.auto_assemble = AutoAssemble::OnSave,
+                "edit" => self.auto_assemble = AutoAssemble::OnEdit,
+                _ => (),
+            },
+            "log_limit" => {
+                if let Some(limit) = value.parse().ok().filter(|limit| *limit > 0) {
+                    self.log_limit = limit;
+                }
+            }
+            "clear_io_on_reset" => {
+                if let Ok(clear_io_on_reset) = value.parse() {
+                    self.clear_io_on_reset = clear_io_on_reset;
+                }
+            }
+            "auto_scroll" => {
+                if let Ok(auto_scroll) = value.parse() {
+                    self.auto_scroll = auto_scroll;
+                }
+            }
+            "show_addresses" => {
+                if let Ok(show_addresses) = value.parse() {
+                    self.show_addresses = show_addresses;
+                }
+            }
+            "show_whitespace" => {
+                if let Ok(show_whitespace) = value.parse() {
+                    self.show_whitespace = show_whitespace;
+                }
+            }
+            "register_heatmap" => {
+                if let Ok(register_heatmap) = value.parse() {
+                    self.register_heatmap = register_heatmap;
+                }
+            }
+            "indent_width" => {
+                if let Some(width) = value.parse().ok().filter(|w| INDENT_WIDTHS.contains(w)) {
+                    self.indent_width = width;
+                }
+            }
+            "indent_tabs" => {
+                if let Ok(indent_tabs) = value.parse() {
+                    self.indent_tabs = indent_tabs;
+                }
+            }
+            "show_register_numbers" => {
+                if let Ok(show_register_numbers) = value.parse() {
+                    self.show_register_numbers = show_register_numbers;
+                }
+            }
+            "memory_refresh_ms" => {
+                if let Ok(ms) = value.parse() {
+                    self.memory_refresh_ms = ms;
+                }
+            }
+            "clock" => match value {
+                "system" => self.proc.clock = ClockMode::System,
+                _ => {
+                    if let Ok(step) = value.parse() {
+                        self.proc.clock = ClockMode::Fixed(step);
+                    }
+                }
+            },
+            "seed" => match value {
+                "none" => self.proc.seed = None,
+                _ => {
+                    if let Ok(seed) = value.parse() {
+                        self.proc.seed = Some(seed);
+                    }
+                }
+            },
+            "trace" => {
+                if let Ok(trace) = value.parse() {
+                    self.proc.trace = trace;
+                }
+            }
+            "run_batch" => {
+                if let Some(run_batch) = value.parse().ok().filter(|batch| *batch > 0) {
+                    self.proc.run_batch = run_batch;
+                }
+            }
+            "max_steps" => {
+                if let Ok(max_steps) = value.parse() {
+                    self.proc.max_steps = max_steps;
+                }
+            }
+            "delay_slots" => {
+                if let Ok(delay_slots) = value.parse() {
+                    self.proc.delay_slots = delay_slots;
+                }
+            }
+            "coverage" => {
+                if let Ok(coverage) = value.parse() {
+                    self.proc.coverage = coverage;
+                }
+            }
+            "track_writers" => {
+                if let Ok(track_writers) = value.parse() {
+                    self.proc.track_writers = track_writers;
+                }
+            }
+            "log_expansions" => {
+                if let Ok(log_expansions) = value.parse() {
+                    self.log_expansions = log_expansions;
+                }
+            }
+            "initial_a0" | "initial_a1" | "initial_a2" | "initial_a3" | "initial_sp" => {
+                let Some(value) = value
+                    .strip_prefix("0x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                else {
+                    return;
+                };
+
+                match key
+                    .strip_prefix("initial_a")
+                    .and_then(|i| i.parse::<usize>().ok())
+                {
+                    Some(i) => self.proc.initial_args[i] = value,
+                    None => self.proc.initial_sp = value,
+                }
+            }
+            "ktext_addr" | "kdata_addr" => {
+                let Some(addr) = value
+                    .strip_prefix("0x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                else {
+                    return;
+                };
+
+                match key {
+                    "ktext_addr" => self.assemble.ktext_addr = addr as usize & !3,
+                    _ => self.assemble.kdata_addr = addr as usize,
+                }
+            }
+            "lint_callee_saved" => {
+                if let Ok(lint_callee_saved) = value.parse() {
+                    self.assemble.lint_callee_saved = lint_callee_saved;
+                }
+            }
+            "lint_unreachable" => {
+                if let Ok(lint_unreachable) = value.parse() {
+                    self.assemble.lint_unreachable = lint_unreachable;
+                }
+            }
+            "case_insensitive" => {
+                if let Ok(case_insensitive) = value.parse() {
+                    self.assemble.case_insensitive = case_insensitive;
+                }
+            }
+            _ => {
+                let Some((theme, kind)) = key
+                    .strip_prefix("highlight.")
+                    .and_then(|key| key.split_once('.'))
+                else {
+                    return;
+                };
+
+                let highlight = match theme {
+                    "dark" => &mut self.highlight_dark,
+                    "light" => &mut self.highlight_light,
+                    _ => return,
+                };
+
+                if let Some((_, color)) = highlight
+                    .colors_mut()
+                    .into_iter()
+                    .find(|(name, _)| *name == kind)
+                {
+                    *color = color_from_hex(value).unwrap_or(*color);
+                }
+            }
+        }
+    }
+
+    /// The optional highlighting of the editor.
+    pub fn highlight_options(&self) -> HighlightOptions {
+        HighlightOptions {
+            show_whitespace: self.show_whitespace,
+            register_heatmap: self.register_heatmap,
+        }
+    }
+
+    /// The indent inserted when pressing Tab in the editor.
+    pub fn indent(&self) -> String {
+        if self.indent_tabs {
+            "\t".to_owned()
+        } else {
+            " ".repeat(self.indent_width)
+        }
+    }
+
+    /// The syntax highlighting colors of the current theme.
+    pub fn highlight(&self) -> &HighlightTheme {
+        match self.theme {
+            Theme::Dark => &self.highlight_dark,
+            Theme::Light => &self.highlight_light,
+        }
+    }
+
+    /// The syntax highlighting colors of the current theme, mutably.
+    pub fn highlight_mut(&mut self) -> &mut HighlightTheme {
+        match self.theme {
+            Theme::Dark => &mut self.highlight_dark,
+            Theme::Light => &mut self.highlight_light,
+        }
+    }
+
+    /// Show the settings window, if it is open.
+    pub fn show(app: &mut App, ctx: &egui::Context) {
+        let mut open = app.settings_open;
+        let prev = app.settings.clone();
+
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let proc = &mut app.settings.proc;
+
+                ui.heading("Processor");
+
+                ui.horizontal(|ui| {
+                    ui.label("System time clock");
+
+                    let fixed = matches!(proc.clock, ClockMode::Fixed(_));
+                    if ui.radio(!fixed, "Wall clock").clicked() {
+                        proc.clock = ClockMode::System;
+                    }
+                    if ui.radio(fixed, "Fixed step").clicked() && !fixed {
+                        proc.clock = ClockMode::Fixed(1);
+                    }
+
+                    if let ClockMode::Fixed(step) = &mut proc.clock {
+                        ui.add(egui::DragValue::new(step).suffix(" ms"));
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let mut fixed = proc.seed.is_some();
+                    if ui.checkbox(&mut fixed, "Fixed random seed").changed() {
+                        proc.seed = fixed.then_some(0);
+                    }
+
+                    if let Some(seed) = &mut proc.seed {
+                        ui.add(egui::DragValue::new(seed));
+                    }
+                })
+                .response
+                .on_hover_text("Applied when the processor is reset or assembled.");
+
+                ui.horizontal(|ui| {
+                    ui.label("Run mode batch size");
+                    ui.add(
+                        egui::DragValue::new(&mut proc.run_batch)
+                            .clamp_range(1..=1_000_000)
+                            .suffix(" instructions"),
+                    );
+                })
+                .response
+                .on_hover_text("Instructions run between updates. Lower is more responsive.");
+
+                ui.horizontal(|ui| {
+                    ui.label("Run mode step limit");
+                    ui.add(
+                        egui::DragValue::new(&mut proc.max_steps)
+                            .clamp_range(0..=u64::MAX)
+                            .suffix(" instructions"),
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Halts a run that takes longer, e.g. an infinite loop. Zero is unlimited.",
+                );
+
+                ui.label("Initial registers")
+                    .on_hover_text("Applied when the processor is reset or assembled.");
+                egui::Grid::new("grid_initial_regs").show(ui, |ui| {
+                    let regs = proc
+                        .initial_args
+                        .iter_mut()
+                        .enumerate()
+                        .map(|(i, value)| (Registers::name(REG_A0 as usize + i), value))
+                        .chain([("sp", &mut proc.initial_sp)]);
+
+                    for (name, value) in regs {
+                        ui.monospace(format!("${name}"));
+                        ui.add(
+                            egui::DragValue::new(value)
+                                .hexadecimal(8, false, false)
+                                .prefix("0x"),
+                        );
+                        ui.end_row();
+                    }
+                });
+
+                ui.checkbox(&mut proc.delay_slots, "Branch delay slots")
+                    .on_hover_text("The instruction after a branch or jump runs before it lands.");
+
+                ui.checkbox(&mut proc.trace, "Record execution trace")
+                    .on_hover_text("Saved with Run > Save Trace. Slows down Run mode.");
+
+                ui.checkbox(&mut proc.track_writers, "Track the writer of each word")
+                    .on_hover_text("Shown when hovering memory. Uses more memory.");
+
+                ui.checkbox(&mut proc.coverage, "Show coverage")
+                    .on_hover_text("Tints the lines that have executed since the last reset.");
+
+                ui.separator();
+                ui.heading("Assembler");
+
+                ui.checkbox(
+                    &mut app.settings.assemble.case_insensitive,
+                    "Case-insensitive mnemonics and registers",
+                )
+                .on_hover_text("Accepts e.g. ADD $T0, $T1, $T2. Labels stay case-sensitive.");
+
+                ui.horizontal(|ui| {
+                    ui.label("Auto-assemble");
+
+                    let auto_assemble = &mut app.settings.auto_assemble;
+                    ui.radio_value(auto_assemble, AutoAssemble::Off, "Off");
+                    ui.radio_value(auto_assemble, AutoAssemble::OnSave, "On save");
+                    ui.radio_value(auto_assemble, AutoAssemble::OnEdit, "On edit");
+                })
+                .response
+                .on_hover_text("Errors are only logged. Skipped while the program is running.");
+
+                ui.checkbox(
+                    &mut app.settings.log_expansions,
+                    "Log pseudo instruction expansions",
+                );
+
+                ui.checkbox(
+                    &mut app.settings.assemble.lint_callee_saved,
+                    "Warn about unsaved callee-saved registers",
+                )
+                .on_hover_text("Checks functions called with jal for $s0-$s7, $fp, $ra and $sp.");
+
+                ui.checkbox(
+                    &mut app.settings.assemble.lint_unreachable,
+                    "Warn about unreachable code",
+                )
+                .on_hover_text(
+                    "Checks for code after j, jr, b or an exit syscall before the next label.",
+                );
+
+                egui::Grid::new("grid_kernel_addrs").show(ui, |ui| {
+                    let assemble = &mut app.settings.assemble;
+                    for (name, addr, align) in [
+                        (".ktext address", &mut assemble.ktext_addr, 4),
+                        (".kdata address", &mut assemble.kdata_addr, 1),
+                    ] {
+                        ui.label(name);
+                        ui.add(
+                            egui::DragValue::new(addr)
+                                .hexadecimal(8, false, false)
+                                .prefix("0x")
+                                .clamp_range(0..=u32::MAX),
+                        );
+                        *addr -= *addr % align;
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.heading("Editor");
+
+                ui.checkbox(&mut app.settings.auto_scroll, "Scroll to the PC line")
+                    .on_hover_text("Scrolls the editor whenever the PC changes.");
+
+                ui.checkbox(
+                    &mut app.settings.show_addresses,
+                    "Show instruction addresses",
+                )
+                .on_hover_text("Shows the address of each instruction line once assembled.");
+
+                ui.checkbox(&mut app.settings.show_whitespace, "Show whitespace")
+                    .on_hover_text("Marks tabs and trailing whitespace in the editor.");
+
+                ui.checkbox(&mut app.settings.indent_tabs, "Indent with tabs")
+                    .on_hover_text("Inserts a tab rather than spaces when pressing Tab.");
+
+                ui.add_enabled_ui(!app.settings.indent_tabs, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Indent width");
+                        ui.add(
+                            egui::DragValue::new(&mut app.settings.indent_width)
+                                .clamp_range(INDENT_WIDTHS)
+                                .suffix(" spaces"),
+                        );
+                    });
+                });
+
+                ui.separator();
+                ui.heading("Registers");
+
+                ui.checkbox(
+                    &mut app.settings.show_register_numbers,
+                    "Show register numbers",
+                )
+                .on_hover_text("Shows e.g. $t0 as $t0 ($8).");
+
+                ui.separator();
+                ui.heading("Memory");
+
+                ui.horizontal(|ui| {
+                    ui.label("Refresh interval while active");
+                    ui.add(
+                        egui::DragValue::new(&mut app.settings.memory_refresh_ms)
+                            .clamp_range(0..=10_000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("Zero refreshes the memory viewer every frame.");
+                });
+
+                ui.separator();
+                ui.heading("Output");
+
+                ui.horizontal(|ui| {
+                    ui.label("Log limit");
+                    ui.add(
+                        egui::DragValue::new(&mut app.settings.log_limit)
+                            .clamp_range(1..=100_000)
+                            .suffix(" lines"),
+                    );
+                });
+
+                ui.checkbox(
+                    &mut app.settings.clear_io_on_reset,
+                    "Clear program I/O on reset",
+                )
+                .on_hover_text("Otherwise, a separator line marks where the new run begins.");
+
+                ui.separator();
+                ui.heading("Syntax colors");
+
+                let theme = app.settings.theme;
+                let highlight = app.settings.highlight_mut();
+                let mut changed = false;
+
+                egui::Grid::new("grid_highlight_colors").show(ui, |ui| {
+                    for (kind, color) in highlight.colors_mut() {
+                        ui.label(kind);
+                        changed |= egui::color_picker::color_edit_button_srgba(
+                            ui,
+                            color,
+                            egui::color_picker::Alpha::Opaque,
+                        )
+                        .changed();
+                        ui.end_row();
+                    }
+                });
+
+                if ui.button("Reset colors").clicked() {
+                    *highlight = theme.default_highlight();
+                    changed = true;
+                }
+
+                if changed {
+                    app.settings.highlight_version += 1;
+                }
+            });
+
+        app.settings_open = open;
+
+        if app.settings.log_limit != prev.log_limit {
+            app.output.log.set_limit(app.settings.log_limit);
+        }
+
+        if app.settings.proc != prev.proc {
+            app.proc_tx
+                .send(ProcMessage::Settings(app.settings.proc.clone()))
+                .unwrap();
+        }
+
+        if app.settings != prev {
+            app.save_settings();
+        }
+    }
+}
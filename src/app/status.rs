@@ -0,0 +1,55 @@
+use crate::simulator::ProcMessage;
+
+use super::App;
+
+/// Show the status bar at the bottom of the window, with the processor
+/// state, the PC and its source line, and the number of instructions run.
+/// While Run mode is executing, a spinner and a Stop button are shown too,
+/// and when the active editor's file changes on disk, an offer to reload it.
+pub fn show_status_bar(app: &mut App, ctx: &egui::Context) {
+    let (mut reload, mut ignore) = (false, false);
+
+    egui::TopBottomPanel::bottom("panel_status").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if app.editor().changed_on_disk {
+                ui.colored_label(ui.visuals().warn_fg_color, "File changed on disk");
+                reload = ui
+                    .small_button("Reload")
+                    .on_hover_text("Discards the changes in the editor.")
+                    .clicked();
+                ignore = ui.small_button("Keep Mine").clicked();
+                ui.separator();
+            }
+
+            let proc = &app.proc;
+            let state = match (proc.loaded, proc.active, proc.running) {
+                (false, _, _) => "Not loaded",
+                (true, true, true) => "Running",
+                (true, true, false) => "Paused",
+                (true, false, _) => "Finished",
+            };
+            if proc.running {
+                ui.spinner();
+            }
+            ui.label(state);
+            if proc.running && ui.small_button("Stop").clicked() {
+                app.proc_tx.send(ProcMessage::Stop).unwrap();
+            }
+            ui.separator();
+
+            ui.monospace(format!("PC 0x{:08x}", proc.pc));
+            if let Some(line) = proc.pc_lines.as_ref().and_then(|lines| lines.get(&proc.pc)) {
+                ui.label(format!("line {}", line + 1));
+            }
+            ui.separator();
+
+            ui.label(format!("{} instructions", proc.inst_count));
+        });
+    });
+
+    if reload {
+        app.reload_file();
+    } else if ignore {
+        app.ignore_disk_change();
+    }
+}
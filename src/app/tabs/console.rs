@@ -0,0 +1,102 @@
+use egui::{Color32, Key, Modifiers};
+
+use crate::{
+    app::ProcState,
+    assembler::{assemble, AssembleOptions},
+    simulator::{ProcMessage, ProcTx},
+};
+
+/// A console for running instructions against the current processor state
+/// without editing the program.
+#[derive(Debug, Default)]
+pub struct Console {
+    pub input: String,
+
+    /// The instructions entered and their results.
+    pub output: Vec<String>,
+}
+
+impl Console {
+    /// Assemble the input and send it to the processor to execute.
+    fn submit(&mut self, proc: &ProcState, proc_tx: &ProcTx, options: &AssembleOptions) {
+        let input = std::mem::take(&mut self.input);
+        if input.trim().is_empty() {
+            return;
+        }
+
+        for line in input.lines() {
+            self.output.push(format!("> {line}"));
+        }
+
+        if proc.running {
+            self.output
+                .push("Error: the program is running, stop it first".to_owned());
+            return;
+        }
+
+        let program = match assemble(&input, options) {
+            Ok(program) => program,
+            Err(e) => {
+                self.output.push(format!("Error: {e}"));
+                return;
+            }
+        };
+
+        if !program.data.is_empty() || !program.ktext.is_empty() || !program.kdata.is_empty() {
+            self.output
+                .push("Error: only instructions can be run here".to_owned());
+            return;
+        }
+
+        for warning in program.warnings {
+            self.output.push(format!("Warning: {warning}"));
+        }
+
+        if !program.text.is_empty() {
+            proc_tx.send(ProcMessage::Execute(program.text)).unwrap();
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        proc: &ProcState,
+        proc_tx: &ProcTx,
+        options: &AssembleOptions,
+    ) {
+        let id = ui.id().with("console_input");
+
+        // Enter runs the input, Shift+Enter starts a new line
+        let submit = ui.memory(|m| m.has_focus(id))
+            && ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Enter));
+        if submit {
+            self.submit(proc, proc_tx, options);
+        }
+
+        egui::TopBottomPanel::bottom("panel_console_input")
+            .frame(egui::Frame::none())
+            .show_inside(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.input)
+                        .id(id)
+                        .code_editor()
+                        .desired_rows(1)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("Type an instruction and press Enter..."),
+                );
+            });
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in self.output.iter() {
+                    let mut text = egui::RichText::new(line).monospace();
+                    if !line.starts_with("> ") {
+                        text = text.color(Color32::GRAY);
+                    }
+                    ui.label(text);
+                }
+            });
+    }
+}
@@ -1,23 +1,127 @@
-use egui::Color32;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use egui::{
+    text::CCursor,
+    text_edit::{CCursorRange, TextEditOutput},
+    Color32,
+};
+use instant::Instant;
 
 use crate::{
-    app::highlighting::highlight,
+    app::highlighting::{highlight, HighlightTheme},
     assembler::{
+        assemble,
         directive::DIRECTIVE_NAMES,
+        disasm,
         inst::{INST_MNEMONICS, PSEUDO_INST_MNEMONICS},
         lexer::{Lexeme, LexemeKind},
     },
+    simulator::{ProcMessage, RegisterHint, Registers, ADDR_STATIC, ADDR_TEXT},
     App,
 };
 
 pub trait LexemeHint {
-    fn show(&self, ui: &mut egui::Ui);
+    fn show(&self, ui: &mut egui::Ui, theme: &HighlightTheme);
+}
+
+/// A hint for a label, showing its resolved address and, for code labels,
+/// the instruction it points to.
+pub struct LabelHint<'a> {
+    pub name: &'a str,
+    pub addr: Option<usize>,
+    pub inst: Option<String>,
+}
+
+impl LexemeHint for LabelHint<'_> {
+    fn show(&self, ui: &mut egui::Ui, _theme: &HighlightTheme) {
+        ui.label(egui::RichText::new(self.name).monospace().strong());
+
+        match self.addr {
+            Some(addr) => {
+                ui.monospace(format!("0x{addr:08x}"));
+            }
+            None => {
+                ui.label(egui::RichText::new("unresolved").color(Color32::GRAY));
+            }
+        }
+
+        if let Some(inst) = &self.inst {
+            ui.monospace(inst);
+        }
+
+        ui.label(egui::RichText::new("Ctrl+click to go to definition").color(Color32::GRAY));
+    }
+}
+
+/// The tint of lines that have executed, when coverage is shown.
+const COVERAGE_COLOR: Color32 = Color32::from_rgba_premultiplied(0, 40, 0, 20);
+
+/// The space between the address margin and the editor text.
+const ADDRESS_MARGIN_GAP: f32 = 8.0;
+
+/// Convert a char index in `text` to a byte index.
+pub fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len())
+}
+
+/// The contents of one editor tab.
+#[derive(Debug, Default)]
+pub struct EditorBuffer {
+    pub body: String,
+
+    /// The file the buffer was loaded from or last saved to.
+    pub file: Option<PathBuf>,
+    pub unsaved: bool,
+
+    /// The modified time of `file` when it was last loaded or saved.
+    pub disk_modified: Option<SystemTime>,
+
+    /// Whether `file` has been modified by something else since then.
+    pub changed_on_disk: bool,
+
+    /// The PC the editor last scrolled to, so it only scrolls when it moves.
+    pub scrolled_pc: Option<usize>,
+
+    /// A line, counting from 0, to move the cursor to on the next frame.
+    pub goto_line: Option<usize>,
 }
 
 pub struct Editor;
 
 impl Editor {
-    pub fn show_lexeme_hint(ui: &mut egui::Ui, app: &App, lexeme: &Lexeme) {
+    /// The id of the `TextEdit` of the editor tab at `index`.
+    pub fn id(index: usize) -> egui::Id {
+        egui::Id::new("editor_body").with(index)
+    }
+
+    /// The title of the editor tab at `index`, the name of its file if it has one.
+    pub fn title(app: &App, index: usize) -> String {
+        let name = app.editors[index]
+            .file
+            .as_ref()
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+
+        match name {
+            Some(name) => name,
+            None if index == 0 => "Editor".to_owned(),
+            None => format!("Editor {}", index + 1),
+        }
+    }
+
+    pub fn show_lexeme_hint(ui: &mut egui::Ui, app: &App, index: usize, lexeme: &Lexeme) {
+        let body = &app.editors[index].body;
+        let register_hint;
+        let label_hint;
+
         let hint: &dyn LexemeHint = match lexeme {
             Lexeme {
                 kind: LexemeKind::Inst,
@@ -25,7 +129,8 @@ impl Editor {
                 ..
             } => {
                 // instructions
-                let value = &app.body[slice.clone()];
+                let value = body[slice.clone()].to_ascii_lowercase();
+                let value = value.as_str();
                 let hint = INST_MNEMONICS
                     .get(value)
                     .map(|v| *v as &dyn LexemeHint)
@@ -47,7 +152,7 @@ impl Editor {
                 ..
             } => {
                 // directives
-                let value = &app.body[slice.clone()];
+                let value = &body[slice.clone()];
                 let hint = DIRECTIVE_NAMES.get(value).map(|v| *v as &dyn LexemeHint);
 
                 match hint {
@@ -56,45 +161,457 @@ impl Editor {
                 }
             }
 
+            Lexeme {
+                kind: LexemeKind::Reg,
+                ref slice,
+                ..
+            } => {
+                // registers
+                let value = &body[slice.clone()];
+                let index = match value
+                    .strip_prefix('$')
+                    .and_then(|name| Registers::index(&name.to_ascii_lowercase()))
+                {
+                    Some(index) if index < 32 => index,
+                    _ => return,
+                };
+
+                register_hint = RegisterHint {
+                    index,
+                    value: app.proc.regs[index],
+                };
+                &register_hint
+            }
+
+            Lexeme {
+                kind: LexemeKind::Label,
+                ref slice,
+                ..
+            } => {
+                // labels, both definitions and references
+                let value = &body[slice.clone()];
+                let name = value.strip_suffix(':').unwrap_or(value);
+
+                // numeric local labels resolve differently per reference
+                if name.starts_with(|c: char| c.is_ascii_digit()) {
+                    return;
+                }
+
+                let addr = app
+                    .proc
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(name).copied());
+
+                let inst = addr
+                    .filter(|addr| (ADDR_TEXT..ADDR_STATIC).contains(addr))
+                    .and_then(|addr| {
+                        let mut buf = [0u8; 4];
+                        let mem = app.proc.mem.try_read()?;
+                        mem.read_view(addr, &mut buf).ok()?;
+                        disasm::disassemble(u32::from_be_bytes(buf))
+                    });
+
+                label_hint = LabelHint { name, addr, inst };
+                &label_hint
+            }
+
             _ => return,
         };
 
         egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("tooltip_lexeme_hover"), |ui| {
-            hint.show(ui)
+            hint.show(ui, app.settings.highlight())
         });
     }
 
-    pub fn show(app: &mut App, ui: &mut egui::Ui) {
+    /// Handle Enter and Tab in the editor before the `TextEdit` sees them,
+    /// keeping the indentation of the current line on new lines and
+    /// inserting the configured indent on Tab.
+    fn handle_indent(app: &mut App, ui: &mut egui::Ui, index: usize) {
+        let id = Self::id(index);
+        if !ui.memory(|m| m.has_focus(id)) {
+            return;
+        }
+
+        let Some(mut state) = egui::text_edit::TextEditState::load(ui.ctx(), id) else {
+            return;
+        };
+        let Some(range) = state.ccursor_range() else {
+            return;
+        };
+
+        let indent = app.settings.indent();
+        let mut inserts = vec![];
+        ui.input_mut(|input| {
+            input.events.retain(|event| match event {
+                egui::Event::Key {
+                    key: egui::Key::Enter,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } if modifiers.is_none() => {
+                    inserts.push(None);
+                    false
+                }
+                egui::Event::Key {
+                    key: egui::Key::Tab,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } if modifiers.is_none() => {
+                    inserts.push(Some(indent.as_str()));
+                    false
+                }
+                _ => true,
+            })
+        });
+
+        if inserts.is_empty() {
+            return;
+        }
+
+        let buffer = &mut app.editors[index];
+        let [start, end] = range.sorted();
+        let mut cursor = start.index;
+        buffer.body.replace_range(
+            byte_index(&buffer.body, start.index)..byte_index(&buffer.body, end.index),
+            "",
+        );
+
+        for insert in inserts {
+            let at = byte_index(&buffer.body, cursor);
+            let text = match insert {
+                Some(indent) => indent.to_owned(),
+                None => {
+                    // keep the leading whitespace of the current line
+                    let line_start = buffer.body[..at].rfind('\n').map(|i| i + 1).unwrap_or(0);
+                    let indent: String = buffer.body[line_start..at]
+                        .chars()
+                        .take_while(|c| *c == ' ' || *c == '\t')
+                        .collect();
+                    format!("\n{indent}")
+                }
+            };
+
+            buffer.body.insert_str(at, &text);
+            cursor += text.chars().count();
+        }
+
+        state.set_ccursor_range(Some(egui::text_edit::CCursorRange::one(
+            egui::text::CCursor::new(cursor),
+        )));
+        state.store(ui.ctx(), id);
+        buffer.unsaved = true;
+    }
+
+    /// Move the cursor to the definition of the label `lexeme` refers to,
+    /// scrolling it into view.
+    fn goto_definition(
+        body: &str,
+        ui: &egui::Ui,
+        editor: &TextEditOutput,
+        lexemes: &BTreeMap<usize, Lexeme>,
+        lexeme: &Lexeme,
+    ) {
+        if lexeme.kind != LexemeKind::Label {
+            return;
+        }
+
+        let text = &body[lexeme.slice.clone()];
+        let name = text.strip_suffix(':').unwrap_or(text);
+
+        // numeric local labels are defined many times
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            return;
+        }
+
+        let Some(def) = lexemes.values().find(|l| {
+            l.kind == LexemeKind::Label && body[l.slice.clone()].strip_suffix(':') == Some(name)
+        }) else {
+            return;
+        };
+
+        let ccursor = CCursor::new(body[..def.slice.start].chars().count());
+        let mut state = editor.state.clone();
+        state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
+        state.store(ui.ctx(), editor.response.id);
+
+        let rect = editor
+            .galley
+            .pos_from_cursor(&editor.galley.from_ccursor(ccursor));
+        ui.scroll_to_rect(rect.translate(editor.text_draw_pos.to_vec2()), None);
+    }
+
+    /// Move the cursor to the start of `line`, counting from 0, scrolling it
+    /// into view. Lines past the end go to the last line.
+    fn goto_line(body: &str, ui: &egui::Ui, editor: &TextEditOutput, line: usize) {
+        let start = body
+            .match_indices('\n')
+            .take(line)
+            .last()
+            .map(|(i, _)| i + 1)
+            .unwrap_or(0);
+
+        let ccursor = CCursor::new(body[..start].chars().count());
+        let mut state = editor.state.clone();
+        state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
+        state.store(ui.ctx(), editor.response.id);
+        editor.response.request_focus();
+
+        let rect = editor
+            .galley
+            .pos_from_cursor(&editor.galley.from_ccursor(ccursor));
+        ui.scroll_to_rect(rect.translate(editor.text_draw_pos.to_vec2()), None);
+    }
+
+    /// The lines the selection covers, or the cursor's line, as their byte
+    /// range in `body` and their line numbers.
+    fn selected_lines(
+        body: &str,
+        editor: &TextEditOutput,
+    ) -> Option<(Range<usize>, RangeInclusive<u32>)> {
+        let [start, end] = editor.state.ccursor_range()?.sorted();
+        let start = byte_index(body, start.index);
+        let mut end = byte_index(body, end.index);
+
+        // a selection ending at the start of a line does not include it
+        if end > start && body[..end].ends_with('\n') {
+            end -= 1;
+        }
+
+        let line = |at: usize| body[..at].matches('\n').count() as u32;
+        let lines = line(start)..=line(end);
+
+        let start = body[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = body[end..]
+            .find('\n')
+            .map(|i| end + i)
+            .unwrap_or(body.len());
+
+        Some((start..end, lines))
+    }
+
+    /// Copy the encoded words of the selected lines, or the cursor's line, to
+    /// the clipboard as hex. The whole body is assembled, so labels resolve as
+    /// they would when running it.
+    fn copy_machine_code(app: &App, ui: &egui::Ui, index: usize, editor: &TextEditOutput) {
+        let body = &app.editors[index].body;
+        let Some((_, lines)) = Self::selected_lines(body, editor) else {
+            return;
+        };
+
+        let words = match assemble(body, &app.settings.assemble) {
+            Ok(program) => program.line_words(lines),
+            Err(e) => {
+                app.log(format!("Assemble error: {e}"));
+                return;
+            }
+        };
+
+        if words.is_empty() {
+            app.log("No instructions on the selected lines");
+            return;
+        }
+
+        let text = words
+            .iter()
+            .map(|word| format!("{word:08x}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        ui.output_mut(|o| o.copied_text = text);
+        app.log(format!(
+            "Copied {} word{} of machine code",
+            words.len(),
+            if words.len() == 1 { "" } else { "s" },
+        ));
+    }
+
+    /// Assemble only the selected lines, or the cursor's line, and run them in
+    /// a throwaway processor, with the results shown in the console.
+    fn run_selection(app: &mut App, index: usize, editor: &TextEditOutput) {
+        let body = &app.editors[index].body;
+        let Some((range, lines)) = Self::selected_lines(body, editor) else {
+            return;
+        };
+
+        // pad with the lines before the selection, so errors and warnings
+        // give the same line numbers as the editor
+        let source = "\n".repeat(*lines.start() as usize) + &body[range];
+
+        app.console.output.push(format!(
+            "> run lines {}-{}",
+            lines.start() + 1,
+            lines.end() + 1
+        ));
+
+        let program = match assemble(&source, &app.settings.assemble) {
+            Ok(program) => program,
+            Err(e) => {
+                app.console.output.push(format!("Error: {e}"));
+                return;
+            }
+        };
+
+        for warning in program.warnings.iter() {
+            app.console.output.push(format!("Warning: {warning}"));
+        }
+
+        app.proc_tx.send(ProcMessage::RunIsolated(program)).unwrap();
+    }
+
+    pub fn show(app: &mut App, ui: &mut egui::Ui, index: usize) {
+        let id = Self::id(index);
+        if ui.memory(|m| m.has_focus(id)) {
+            app.active_editor = index;
+        }
+        Self::handle_indent(app, ui, index);
+
+        // the processor state only describes the last assembled buffer
+        let assembled = app.assembled_editor == Some(index);
+
+        let theme = *app.settings.highlight();
+        let version = app.settings.highlight_version;
+        let options = app.settings.highlight_options();
         let mut layouter = |ui: &egui::Ui, body: &str, _| {
-            let (job, _) = highlight(ui.ctx(), body);
+            let (job, _) = highlight(ui.ctx(), body, &theme, version, options);
             ui.fonts(|f| f.layout_job(job))
         };
 
-        let editor = egui::TextEdit::multiline(&mut app.body)
-            .code_editor()
-            .frame(false)
-            .hint_text("Write some assembly here...")
-            .layouter(&mut layouter)
-            .show(ui);
+        // leave room on the left for the instruction addresses
+        let line_pcs = app
+            .proc
+            .line_pcs
+            .as_ref()
+            .filter(|_| assembled && app.settings.show_addresses);
+        let margin_font = egui::TextStyle::Monospace.resolve(ui.style());
+        let margin_width = line_pcs
+            .map(|_| ui.fonts(|f| f.glyph_width(&margin_font, '0')) * 10.0 + ADDRESS_MARGIN_GAP);
+        let margin_left = ui.max_rect().left();
+
+        let editor = ui
+            .horizontal_top(|ui| {
+                if let Some(width) = margin_width {
+                    ui.add_space(width);
+                }
+
+                egui::TextEdit::multiline(&mut app.editors[index].body)
+                    .id(id)
+                    .code_editor()
+                    .frame(false)
+                    .hint_text("Write some assembly here...")
+                    .layouter(&mut layouter)
+                    .show(ui)
+            })
+            .inner;
+
+        if let Some(line_pcs) = line_pcs {
+            let painter = ui.painter();
+            let color = ui.visuals().weak_text_color();
+
+            for (line, addr) in line_pcs {
+                if let Some(row) = editor.galley.rows.get(*line as usize) {
+                    painter.text(
+                        egui::pos2(margin_left, editor.text_draw_pos.y + row.rect.top()),
+                        egui::Align2::LEFT_TOP,
+                        format!("0x{addr:08x}"),
+                        margin_font.clone(),
+                        color,
+                    );
+                }
+            }
+        }
+
+        // draw a faint glyph over the whitespace the highlighter marked
+        if options.show_whitespace {
+            let painter = ui.painter_at(editor.response.rect);
+            let color = ui.visuals().weak_text_color();
+            let sections = &editor.galley.job.sections;
+
+            for glyph in editor.galley.rows.iter().flat_map(|row| row.glyphs.iter()) {
+                let marked = sections
+                    .get(glyph.section_index as usize)
+                    .is_some_and(|s| s.format.background != Color32::TRANSPARENT);
+                if !marked {
+                    continue;
+                }
+
+                let marker = if glyph.chr == '\t' { "→" } else { "·" };
+                painter.text(
+                    glyph.logical_rect().center() + editor.text_draw_pos.to_vec2(),
+                    egui::Align2::CENTER_CENTER,
+                    marker,
+                    margin_font.clone(),
+                    color,
+                );
+            }
+        }
+
+        let (mut copy_code, mut run_selection) = (false, false);
+        editor.response.clone().context_menu(|ui| {
+            if ui.button("Copy Machine Code").clicked() {
+                copy_code = true;
+                ui.close_menu();
+            }
+            if ui.button("Run Selection").clicked() {
+                run_selection = true;
+                ui.close_menu();
+            }
+        });
+        if copy_code {
+            Self::copy_machine_code(app, ui, index, &editor);
+        }
+        if run_selection {
+            Self::run_selection(app, index, &editor);
+        }
+
+        if let Some(line) = app.editors[index].goto_line.take() {
+            Self::goto_line(&app.editors[index].body, ui, &editor, line);
+        }
 
         if editor.response.changed() {
-            app.unsaved = true;
+            app.editors[index].unsaved = true;
+            app.edited_at = Some(Instant::now());
+        }
+
+        // tint the lines that have executed
+        if assembled && app.settings.proc.coverage {
+            if let Some(pc_lines) = &app.proc.pc_lines {
+                let painter = ui.painter_at(editor.response.rect);
+                let lines = app
+                    .proc
+                    .coverage
+                    .iter()
+                    .filter_map(|pc| pc_lines.get(pc))
+                    .collect::<HashSet<_>>();
+
+                for row in lines
+                    .into_iter()
+                    .filter_map(|line| editor.galley.rows.get(*line as usize))
+                {
+                    let rect = row.rect.translate(editor.text_draw_pos.to_vec2());
+                    painter.rect_filled(rect, 0.0, COVERAGE_COLOR);
+                }
+            }
         }
 
         if let Some(row) = app
             .proc
             .pc_lines
             .as_ref()
+            .filter(|_| assembled)
             .and_then(|map| map.get(&app.proc.pc).copied())
             .and_then(|idx| editor.galley.rows.get(idx as usize))
         {
+            let rect = row.rect.translate(editor.text_draw_pos.to_vec2());
             let painter = ui.painter_at(editor.response.rect);
-            painter.rect_filled(
-                row.rect.translate(editor.text_draw_pos.to_vec2()),
-                0.0,
-                Color32::from_rgba_unmultiplied(255, 0, 0, 20),
-            );
+            painter.rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(255, 0, 0, 20));
+
+            // only scroll when the PC moves, so the user can scroll away
+            if app.settings.auto_scroll && app.editors[index].scrolled_pc != Some(app.proc.pc) {
+                ui.scroll_to_rect(rect, None);
+            }
         }
+        app.editors[index].scrolled_pc = Some(app.proc.pc);
 
         // lexeme hovering
         if let Some(hover_pos) = ui.input(|p| p.pointer.hover_pos()) {
@@ -103,12 +620,23 @@ impl Editor {
                 let hover_cursor = editor.galley.cursor_from_pos(local_pos);
 
                 if editor.galley.rect.contains(local_pos.to_pos2()) {
-                    let (_, lexemes) = highlight(ui.ctx(), &app.body);
+                    let (_, lexemes) =
+                        highlight(ui.ctx(), &app.editors[index].body, &theme, version, options);
 
                     if let Some((_, lexeme)) =
                         lexemes.range(..hover_cursor.ccursor.index).next_back()
                     {
-                        Self::show_lexeme_hint(ui, app, lexeme);
+                        Self::show_lexeme_hint(ui, app, index, lexeme);
+
+                        if editor.response.clicked() && ui.input(|i| i.modifiers.command) {
+                            Self::goto_definition(
+                                &app.editors[index].body,
+                                ui,
+                                &editor,
+                                &lexemes,
+                                lexeme,
+                            );
+                        }
                     }
                 }
             }
@@ -0,0 +1,198 @@
+use egui::{text::LayoutJob, Color32, TextFormat};
+
+use crate::{
+    assembler::{
+        disasm,
+        inst::{InstArg, InstCall},
+        parser::{NodeImm, NodeKind, Parser},
+    },
+    App,
+};
+
+use super::editor::{byte_index, Editor};
+
+/// The color of an encoded field, matching the colors of the arguments in
+/// instruction hints.
+fn field_color(name: &str) -> Color32 {
+    match name {
+        "rs" => InstArg::Rs.to_color(),
+        "rt" => InstArg::Rt.to_color(),
+        "rd" => InstArg::Rd.to_color(),
+        "shamt" => InstArg::Shamt.to_color(),
+        "imm" | "addr" => InstArg::Addr.to_color(),
+        _ => Color32::GRAY,
+    }
+}
+
+/// Show the bit fields of an encoded word, in binary and hex.
+pub fn show_fields(ui: &mut egui::Ui, word: u32) {
+    egui::Grid::new(ui.id().with("grid_fields"))
+        .num_columns(3)
+        .show(ui, |ui| {
+            for (name, value, bits) in disasm::decode_fields(word).layout() {
+                ui.colored_label(field_color(name), name);
+                ui.monospace(format!("{value:0width$b}", width = bits as usize));
+                ui.monospace(format!("0x{value:x}"));
+                ui.end_row();
+            }
+        });
+}
+
+/// The bits of an encoded word, colored by field.
+fn bits_job(word: u32) -> LayoutJob {
+    let mut job = LayoutJob::default();
+
+    for (i, (name, value, bits)) in disasm::decode_fields(word).layout().into_iter().enumerate() {
+        job.append(
+            &format!("{value:0width$b}", width = bits as usize),
+            if i == 0 { 0.0 } else { 6.0 },
+            TextFormat {
+                color: field_color(name),
+                font_id: egui::FontId::monospace(14.0),
+                ..Default::default()
+            },
+        );
+    }
+
+    job
+}
+
+/// The instruction call of a node, without resolving labels, and a note on
+/// how the encoding may differ once assembled. Errors with why the node
+/// can't be encoded on its own.
+fn node_call(kind: &NodeKind) -> Result<(InstCall, Option<&'static str>), &'static str> {
+    const UNRESOLVED: &str = "Labels are resolved when assembling, so they encode as zero here.";
+
+    let call = |inst, rs, rt, rd, shamt, imm| InstCall {
+        inst,
+        rs,
+        rt,
+        rd,
+        shamt,
+        imm,
+    };
+
+    match kind {
+        NodeKind::InstR {
+            inst,
+            rs,
+            rt,
+            rd,
+            shamt,
+        } => Ok((call(inst, *rs, *rt, *rd, *shamt, 0), None)),
+
+        NodeKind::InstI { inst, rs, rt, imm } => match imm {
+            NodeImm::Half(half) => Ok((call(inst, *rs, *rt, 0, 0, *half as u32), None)),
+            NodeImm::Addr(value) if *value <= u16::MAX as u32 => {
+                Ok((call(inst, *rs, *rt, 0, 0, *value), None))
+            }
+            NodeImm::Addr(_) => Err(
+                "The immediate does not fit in 16 bits, so this expands into several instructions.",
+            ),
+            NodeImm::Label(_) | NodeImm::Expr(_) => {
+                Ok((call(inst, *rs, *rt, 0, 0, 0), Some(UNRESOLVED)))
+            }
+        },
+
+        NodeKind::InstJ { inst, addr } => match addr {
+            NodeImm::Half(half) => Ok((call(inst, 0, 0, 0, 0, *half as u32 >> 2), None)),
+            NodeImm::Addr(addr) => Ok((call(inst, 0, 0, 0, 0, (addr >> 2) & 0x3ffffff), None)),
+            NodeImm::Label(_) | NodeImm::Expr(_) => {
+                Ok((call(inst, 0, 0, 0, 0, 0), Some(UNRESOLVED)))
+            }
+        },
+
+        NodeKind::InstPseudo { .. } => {
+            Err("Pseudo instructions expand into other instructions when assembling.")
+        }
+
+        _ => Err("Not an instruction."),
+    }
+}
+
+/// The hint shown when the cursor is not on an instruction.
+const NO_INST: &str = "Place the editor cursor on an instruction to see its encoding.";
+
+pub struct EncodingTab;
+
+impl EncodingTab {
+    /// The source line the editor cursor is on, if the editor has a cursor.
+    fn cursor_line<'a>(app: &'a App, ctx: &egui::Context) -> Option<&'a str> {
+        let state = egui::text_edit::TextEditState::load(ctx, Editor::id(app.active_editor))?;
+        let body = &app.editor().body;
+        let cursor = byte_index(body, state.ccursor_range()?.primary.index);
+
+        let start = body[..cursor].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = body[cursor..]
+            .find('\n')
+            .map(|i| cursor + i)
+            .unwrap_or(body.len());
+
+        Some(&body[start..end])
+    }
+
+    pub fn show(app: &App, ui: &mut egui::Ui) {
+        let hint = |ui: &mut egui::Ui, text: &str| {
+            ui.label(egui::RichText::new(text).color(Color32::GRAY));
+        };
+
+        let Some(line) = Self::cursor_line(app, ui.ctx()) else {
+            hint(ui, NO_INST);
+            return;
+        };
+
+        let parser =
+            Parser::new(line).with_case_insensitive(app.settings.assemble.case_insensitive);
+        let nodes = match parser.parse() {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                hint(ui, &e.to_string());
+                return;
+            }
+        };
+
+        // skip any label defined on the same line
+        let Some(node) = nodes.iter().find(|node| {
+            matches!(
+                node.kind,
+                NodeKind::InstR { .. }
+                    | NodeKind::InstI { .. }
+                    | NodeKind::InstJ { .. }
+                    | NodeKind::InstPseudo { .. }
+            )
+        }) else {
+            hint(ui, NO_INST);
+            return;
+        };
+
+        let (call, note) = match node_call(&node.kind) {
+            Ok(call) => call,
+            Err(reason) => {
+                hint(ui, reason);
+                return;
+            }
+        };
+
+        let word = match call.encode() {
+            Ok(word) => word,
+            Err(e) => {
+                hint(ui, &e.to_string());
+                return;
+            }
+        };
+        ui.label(bits_job(word));
+        ui.horizontal(|ui| {
+            ui.monospace(format!("0x{word:08x}"));
+            if let Some(inst) = disasm::disassemble(word) {
+                ui.monospace(egui::RichText::new(inst).color(Color32::GRAY));
+            }
+        });
+        ui.separator();
+
+        show_fields(ui, word);
+
+        if let Some(note) = note {
+            hint(ui, note);
+        }
+    }
+}
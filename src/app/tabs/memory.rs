@@ -1,21 +1,122 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, time::Duration};
 
 use egui::{
     text::LayoutJob,
     util::cache::{ComputerMut, FrameCache},
 };
-use parking_lot::RwLock;
+use instant::Instant;
 
-use crate::simulator::{Memory, ADDR_HEAP, ADDR_MEM_MAX, ADDR_STACK_TOP, ADDR_STATIC, ADDR_TEXT};
+use crate::{
+    app::{ProcState, DIFF_COLOR},
+    assembler::disasm,
+    simulator::{
+        ProcMessage, ProcTx, ADDR_HEAP, ADDR_MEM_MAX, ADDR_STACK_TOP, ADDR_STATIC, ADDR_TEXT,
+        REG_SP,
+    },
+};
+
+use super::encoding;
 
 pub const MEMORY_VIEW_BYTES: usize = 256; // 64 words * 4 bytes
 
+/// The number of bytes in a row of the viewer.
+const ROW_BYTES: usize = 16;
+
+/// The number of bytes per line when copying a selection as a C array.
+const C_ARRAY_LINE_BYTES: usize = 12;
+
 #[derive(Debug)]
 pub struct MemoryViewer {
     pub offset: usize,
     pub cur_offset: usize,
     pub view: [u8; MEMORY_VIEW_BYTES],
     pub request_refresh: bool,
+
+    /// The selected byte range, as the clicked address and the shift-clicked
+    /// address, in either order.
+    pub selection: Option<(usize, usize)>,
+
+    /// How the data is displayed.
+    pub mode: DisplayMode,
+
+    /// The PC of the instruction that last wrote each word in view, by word
+    /// address, if writer tracking is enabled.
+    pub writers: BTreeMap<usize, usize>,
+
+    /// When the view was last read from memory.
+    last_refresh: Option<Instant>,
+
+    /// The fill dialog, while it is open.
+    fill: Option<FillDialog>,
+}
+
+/// The inputs of the dialog filling a range of memory with a value.
+#[derive(Debug)]
+struct FillDialog {
+    start: usize,
+    len: usize,
+    value: u32,
+
+    /// The width of the value in bytes: 1, 2 or 4.
+    width: usize,
+
+    /// Why the last fill failed, if it did.
+    error: Option<String>,
+}
+
+impl FillDialog {
+    /// The value as big-endian bytes of its width.
+    fn pattern(&self) -> Vec<u8> {
+        self.value.to_be_bytes()[4 - self.width..].to_vec()
+    }
+}
+
+/// How the memory viewer displays data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Bytes,
+    HexWords,
+    SignedWords,
+    UnsignedWords,
+}
+
+impl DisplayMode {
+    pub const ALL: &'static [Self] = &[
+        Self::Bytes,
+        Self::HexWords,
+        Self::SignedWords,
+        Self::UnsignedWords,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bytes => "Bytes",
+            Self::HexWords => "Hex words",
+            Self::SignedWords => "Signed words",
+            Self::UnsignedWords => "Unsigned words",
+        }
+    }
+
+    /// The number of bytes shown in each cell.
+    pub fn width(&self) -> usize {
+        match self {
+            Self::Bytes => 1,
+            _ => 4,
+        }
+    }
+
+    /// Format the bytes of a cell, read as big-endian.
+    fn format(&self, bytes: &[u8]) -> String {
+        let word = || u32::from_be_bytes(bytes.try_into().expect("cell is not a word"));
+
+        match self {
+            Self::Bytes => format!("{:02x}", bytes[0]),
+            Self::HexWords => format!("{:08x}", word()),
+            Self::SignedWords => format!("{:>11}", word() as i32),
+            Self::UnsignedWords => format!("{:>10}", word()),
+        }
+    }
 }
 
 impl Default for MemoryViewer {
@@ -25,6 +126,11 @@ impl Default for MemoryViewer {
             cur_offset: ADDR_STATIC,
             view: [0u8; MEMORY_VIEW_BYTES],
             request_refresh: true,
+            selection: None,
+            mode: DisplayMode::default(),
+            writers: BTreeMap::new(),
+            last_refresh: None,
+            fill: None,
         }
     }
 }
@@ -34,14 +140,167 @@ impl MemoryViewer {
         self.request_refresh = true;
     }
 
-    pub fn show(&mut self, ui: &mut egui::Ui, mem: &Arc<RwLock<Memory>>) {
-        if self.request_refresh || self.offset != self.cur_offset {
-            self.request_refresh = false;
-            self.cur_offset = self.offset;
+    /// The selected addresses, from lowest to highest.
+    pub fn selected(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        self.selection
+            .map(|(anchor, end)| anchor.min(end)..=anchor.max(end))
+    }
+
+    /// Read the selected bytes from memory.
+    fn read_selection(&self, proc: &ProcState) -> Option<Vec<u8>> {
+        let range = self.selected()?;
+        let mut bytes = vec![0u8; range.end() - range.start() + 1];
+        proc.mem.read().read_view(*range.start(), &mut bytes).ok()?;
+        Some(bytes)
+    }
 
-            mem.read()
-                .read_view(self.cur_offset, &mut self.view)
-                .expect("failed to read memory");
+    /// Show the fill dialog, if it is open, filling memory when confirmed.
+    fn show_fill(&mut self, ctx: &egui::Context, proc: &ProcState) {
+        let Some(fill) = &mut self.fill else {
+            return;
+        };
+
+        let mut open = true;
+        let mut filled = false;
+
+        egui::Window::new("Fill Memory")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("grid_memory_fill").show(ui, |ui| {
+                    ui.label("Start");
+                    ui.add(
+                        egui::DragValue::new(&mut fill.start)
+                            .hexadecimal(8, false, false)
+                            .prefix("0x")
+                            .clamp_range(0..=ADDR_MEM_MAX - 1),
+                    );
+                    ui.end_row();
+
+                    ui.label("Length");
+                    ui.add(
+                        egui::DragValue::new(&mut fill.len)
+                            .clamp_range(0..=ADDR_MEM_MAX)
+                            .suffix(" bytes"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Value");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut fill.value)
+                                .hexadecimal(fill.width * 2, false, false)
+                                .prefix("0x")
+                                .clamp_range(0..=u32::MAX >> (32 - fill.width * 8)),
+                        );
+
+                        for (width, name) in [(1, "Byte"), (2, "Half"), (4, "Word")] {
+                            if ui.radio_value(&mut fill.width, width, name).changed() {
+                                fill.value &= u32::MAX >> (32 - width * 8);
+                            }
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                if let Some(error) = &fill.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                if ui.button("Fill").clicked() {
+                    let mut mem = proc.mem.write();
+                    mem.writer = None;
+                    match mem.fill(fill.start, fill.len, &fill.pattern()) {
+                        Ok(()) => filled = true,
+                        Err(e) => fill.error = Some(format!("Failed to fill memory: {e}")),
+                    }
+                }
+            });
+
+        if filled {
+            self.request_refresh();
+        }
+        if !open || filled {
+            self.fill = None;
+        }
+    }
+
+    /// Handle keyboard navigation while the pointer is over the viewer and no
+    /// widget has keyboard focus: PageUp/PageDown page through memory, the
+    /// arrow keys move by a row, and Home jumps to the static data.
+    fn handle_keys(&mut self, ui: &egui::Ui) {
+        if !ui.rect_contains_pointer(ui.max_rect()) || ui.memory(|m| m.focus().is_some()) {
+            return;
+        }
+
+        ui.input(|input| {
+            if input.key_pressed(egui::Key::PageUp) {
+                self.set_offset(self.offset.saturating_sub(MEMORY_VIEW_BYTES));
+            }
+            if input.key_pressed(egui::Key::PageDown) {
+                self.set_offset(self.offset + MEMORY_VIEW_BYTES);
+            }
+            if input.key_pressed(egui::Key::ArrowUp) {
+                self.set_offset(self.offset.saturating_sub(ROW_BYTES));
+            }
+            if input.key_pressed(egui::Key::ArrowDown) {
+                self.set_offset(self.offset + ROW_BYTES);
+            }
+            if input.key_pressed(egui::Key::Home) {
+                self.set_offset(ADDR_STATIC);
+            }
+        });
+    }
+
+    /// Move the view to `offset`, aligned to a word and clamped so the whole
+    /// view lies within memory.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset.min(ADDR_MEM_MAX - MEMORY_VIEW_BYTES) & !3;
+    }
+
+    /// Show the viewer. While the processor is active, the view is re-read
+    /// every `refresh_interval`, or every frame if it is zero.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        proc: &mut ProcState,
+        proc_tx: &ProcTx,
+        refresh_interval: Duration,
+    ) {
+        self.handle_keys(ui);
+        self.set_offset(self.offset);
+
+        let due = match self.last_refresh {
+            Some(last) if proc.active => {
+                let elapsed = last.elapsed();
+                if elapsed < refresh_interval {
+                    ui.ctx().request_repaint_after(refresh_interval - elapsed);
+                }
+                elapsed >= refresh_interval
+            }
+            _ => proc.active,
+        };
+
+        if self.request_refresh || due || self.offset != self.cur_offset {
+            // don't block the UI on a busy processor, just try again next frame
+            match proc.mem.try_read() {
+                Some(mem) => {
+                    self.request_refresh = false;
+                    self.last_refresh = Some(Instant::now());
+                    self.cur_offset = self.offset;
+
+                    // the offset is clamped, but show zeros rather than
+                    // panicking if the read is still out of bounds
+                    if mem.read_view(self.cur_offset, &mut self.view).is_err() {
+                        self.view.fill(0);
+                    }
+                    self.writers = mem
+                        .writers(self.cur_offset..self.cur_offset + MEMORY_VIEW_BYTES)
+                        .collect();
+                }
+                None => ui.ctx().request_repaint(),
+            }
         }
 
         egui::ScrollArea::both()
@@ -54,7 +313,7 @@ impl MemoryViewer {
                         .add_enabled(self.offset != 0, egui::Button::new("Previous"))
                         .clicked()
                     {
-                        self.offset = self.offset.saturating_sub(MEMORY_VIEW_BYTES);
+                        self.set_offset(self.offset.saturating_sub(MEMORY_VIEW_BYTES));
                     }
 
                     if ui
@@ -64,7 +323,7 @@ impl MemoryViewer {
                         )
                         .clicked()
                     {
-                        self.offset += MEMORY_VIEW_BYTES;
+                        self.set_offset(self.offset + MEMORY_VIEW_BYTES);
                     }
 
                     if self.offset < ADDR_MEM_MAX >> 2 {
@@ -75,7 +334,7 @@ impl MemoryViewer {
                         }
 
                         if button.clicked() {
-                            self.offset <<= 2;
+                            self.set_offset(self.offset << 2);
                         }
                     }
 
@@ -83,47 +342,214 @@ impl MemoryViewer {
                         .selected_text("Jump to...")
                         .show_ui(ui, |ui| {
                             if ui.button("Text").clicked() {
-                                self.offset = ADDR_TEXT;
+                                self.set_offset(ADDR_TEXT);
                             }
                             if ui.button("Static").clicked() {
-                                self.offset = ADDR_STATIC;
+                                self.set_offset(ADDR_STATIC);
                             }
                             if ui.button("Heap").clicked() {
-                                self.offset = ADDR_HEAP;
+                                self.set_offset(ADDR_HEAP);
                             }
                             if ui.button("Stack Base").clicked() {
-                                self.offset = ADDR_STACK_TOP - MEMORY_VIEW_BYTES;
+                                self.set_offset(ADDR_STACK_TOP - MEMORY_VIEW_BYTES);
+                            }
+                        });
+
+                    if ui.button("Refresh").clicked() {
+                        self.request_refresh();
+                    }
+
+                    if ui.button("Fill...").clicked() {
+                        let (start, len) = match self.selected() {
+                            Some(range) => (*range.start(), range.end() - range.start() + 1),
+                            None => (self.offset, MEMORY_VIEW_BYTES),
+                        };
+                        self.fill = Some(FillDialog {
+                            start,
+                            len,
+                            value: 0,
+                            width: 1,
+                            error: None,
+                        });
+                    }
+
+                    egui::ComboBox::from_id_source("combo_memory_display_mode")
+                        .selected_text(self.mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in DisplayMode::ALL {
+                                ui.selectable_value(&mut self.mode, *mode, mode.name());
                             }
                         });
+
+                    if let Some(range) = self.selected() {
+                        ui.separator();
+                        ui.label(format!(
+                            "{} bytes selected from 0x{:08x}",
+                            range.end() - range.start() + 1,
+                            range.start()
+                        ));
+
+                        if ui.button("Clear").clicked() {
+                            self.selection = None;
+                        }
+                    }
                 });
 
+                // labels in view, by address
+                let mut labels: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+                for (name, addr) in proc.labels.iter().flatten() {
+                    if (self.offset..self.offset + MEMORY_VIEW_BYTES).contains(addr) {
+                        labels.entry(*addr).or_default().push(name);
+                    }
+                }
+                for names in labels.values_mut() {
+                    names.sort_unstable();
+                }
+
+                // the baseline bytes in view, if any
+                let baseline = proc.baseline.as_ref().and_then(|baseline| {
+                    let mut view = [0u8; MEMORY_VIEW_BYTES];
+                    baseline.mem.read_view(self.cur_offset, &mut view).ok()?;
+                    Some(view)
+                });
+
+                // the stack in use, between $sp and the stack top
+                let sp = proc.regs[REG_SP as usize].to_u32() as usize;
+                let stack = (ADDR_HEAP < sp && sp <= ADDR_STACK_TOP).then_some(sp..ADDR_STACK_TOP);
+
+                let selected = self.selected();
+                let width = self.mode.width();
+                let mut copy: Option<fn(&[u8]) -> String> = None;
+
                 egui::Grid::new("grid_memory_viewer")
-                    .num_columns(3)
+                    .num_columns(4)
                     .striped(true)
                     .spacing([20.0, 4.0])
                     .show(ui, |ui| {
                         ui.strong("Addr");
                         ui.strong("Data");
                         ui.strong("Ascii");
+                        ui.strong("Labels");
                         ui.end_row();
 
-                        for chunk in self.view.chunks(16) {
-                            ui.monospace(format!("{:08x}", self.offset + offset));
+                        for chunk in self.view.chunks(ROW_BYTES) {
+                            let row = self.offset + offset;
+                            ui.monospace(format!("{row:08x}"));
                             ui.horizontal(|ui| {
                                 ui.spacing_mut().item_spacing = egui::Vec2::ZERO;
 
-                                for (i, byte) in chunk.iter().enumerate() {
+                                for i in (0..chunk.len()).step_by(width) {
+                                    let addr = self.offset + offset + i;
+                                    let cell = addr..addr + width;
+                                    let bytes = &chunk[i..i + width];
+                                    let watched = proc.watchpoints.range(cell.clone()).next().is_some();
+
                                     let mut text = egui::RichText::new(format!(
-                                        "{byte:02x}{}",
-                                        if i % 4 == 3 { "  " } else { " " }
+                                        "{}{}",
+                                        self.mode.format(bytes),
+                                        if (i + width - 1) % 4 == 3 { "  " } else { " " }
                                     ))
                                     .monospace();
 
-                                    if *byte == 0 {
+                                    let before = baseline
+                                        .as_ref()
+                                        .map(|view| &view[offset + i..offset + i + width])
+                                        .filter(|before| *before != bytes);
+
+                                    if before.is_some() {
+                                        text = text.color(DIFF_COLOR);
+                                    } else if bytes.iter().all(|byte| *byte == 0) {
                                         text = text.color(egui::Color32::DARK_GRAY);
+                                    } else if let Some(color) = segment_color(addr) {
+                                        text = text.color(color);
+                                    }
+
+                                    if watched {
+                                        text = text.background_color(egui::Color32::from_rgba_unmultiplied(255, 200, 0, 40));
+                                    } else if selected.as_ref().is_some_and(|range| cell.clone().any(|addr| range.contains(&addr))) {
+                                        text = text.background_color(ui.visuals().selection.bg_fill);
+                                    } else if stack.as_ref().is_some_and(|stack| stack.contains(&addr)) {
+                                        text = text.background_color(STACK_COLOR);
+                                    }
+
+                                    let label_names = labels.range(cell.clone()).flat_map(|(_, names)| names.iter().copied()).collect::<Vec<_>>();
+                                    if !label_names.is_empty() || cell.contains(&sp) {
+                                        text = text.underline();
                                     }
 
-                                    ui.label(text);
+                                    let mut hover = vec![];
+                                    if !label_names.is_empty() {
+                                        hover.push(label_names.join(", "));
+                                    }
+                                    if cell.contains(&sp) {
+                                        hover.push("$sp".to_owned());
+                                    }
+                                    if let Some(before) = before {
+                                        hover.push(format!("Baseline: {}", self.mode.format(before)));
+                                    }
+                                    for (_, pc) in self.writers.range(cell.start & !3..cell.end) {
+                                        hover.push(match proc.pc_lines.as_ref().and_then(|lines| lines.get(pc)) {
+                                            Some(line) => format!("Written by 0x{pc:08x} (line {})", line + 1),
+                                            None => format!("Written by 0x{pc:08x}"),
+                                        });
+                                    }
+
+                                    let mut response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                                    if !hover.is_empty() {
+                                        response = response.on_hover_text(hover.join("\n"));
+                                    }
+
+                                    if response.clicked() {
+                                        self.selection = match selected.clone() {
+                                            // extend the selection to cover the clicked cell
+                                            Some(range) if ui.input(|i| i.modifiers.shift) => {
+                                                Some(((*range.start()).min(cell.start), (*range.end()).max(cell.end - 1)))
+                                            }
+                                            _ => Some((cell.start, cell.end - 1)),
+                                        };
+                                    }
+
+                                    response.context_menu(|ui| {
+                                        if selected.is_some() {
+                                            if ui.button("Copy as C array").clicked() {
+                                                copy = Some(format_c_array);
+                                                ui.close_menu();
+                                            }
+                                            if ui.button("Copy as hex").clicked() {
+                                                copy = Some(format_hex);
+                                                ui.close_menu();
+                                            }
+                                            ui.separator();
+                                        }
+
+                                        // disassemble the word containing this cell
+                                        let word_addr = addr & !3;
+                                        let mut word = [0u8; 4];
+                                        if let Some(mem) = proc.mem.try_read() {
+                                            let _ = mem.read_view(word_addr, &mut word);
+                                        }
+                                        let word = u32::from_be_bytes(word);
+                                        let inst = disasm::disassemble(word).unwrap_or_else(|| "(not an instruction)".to_owned());
+
+                                        ui.label(egui::RichText::new(format!("0x{word_addr:08x}: {word:08x}")).monospace().color(egui::Color32::GRAY));
+                                        ui.monospace(inst).on_hover_ui(|ui| encoding::show_fields(ui, word));
+                                        ui.separator();
+
+                                        if watched {
+                                            if ui.button("Unwatch").clicked() {
+                                                for addr in cell.clone() {
+                                                    if proc.watchpoints.remove(&addr) {
+                                                        let _ = proc_tx.send(ProcMessage::Unwatch(addr));
+                                                    }
+                                                }
+                                                ui.close_menu();
+                                            }
+                                        } else if ui.button("Watch").clicked() {
+                                            proc.watchpoints.insert(addr);
+                                            let _ = proc_tx.send(ProcMessage::Watch(addr));
+                                            ui.close_menu();
+                                        }
+                                    });
                                 }
                             });
                             ui.label(ui.memory_mut(|m| {
@@ -131,12 +557,79 @@ impl MemoryViewer {
                                     .cache::<FrameCache<LayoutJob, ChunkAscii>>()
                                     .get(chunk)
                             }));
+                            ui.horizontal(|ui| {
+                                if (row..row + chunk.len()).contains(&sp) {
+                                    let tag = match sp - row {
+                                        0 => "$sp".to_owned(),
+                                        byte => format!("$sp +{byte}"),
+                                    };
+                                    ui.label(egui::RichText::new(tag).monospace().strong());
+                                }
+
+                                for (addr, names) in labels.range(row..row + chunk.len()) {
+                                    for name in names {
+                                        let tag = match addr - row {
+                                            0 => format!("{name}:"),
+                                            byte => format!("{name}: +{byte}"),
+                                        };
+                                        ui.label(egui::RichText::new(tag).monospace().color(egui::Color32::GRAY));
+                                    }
+                                }
+                            });
                             ui.end_row();
 
                             offset += chunk.len();
                         }
                     });
+
+                if let Some(format) = copy {
+                    if let Some(bytes) = self.read_selection(proc) {
+                        ui.output_mut(|o| o.copied_text = format(&bytes));
+                    }
+                }
             });
+
+        self.show_fill(ui.ctx(), proc);
+    }
+}
+
+/// Format bytes as a C `uint8_t[]` literal.
+fn format_c_array(bytes: &[u8]) -> String {
+    let mut out = format!("uint8_t data[{}] = {{\n", bytes.len());
+
+    for line in bytes.chunks(C_ARRAY_LINE_BYTES) {
+        let line = line
+            .iter()
+            .map(|byte| format!("0x{byte:02x},"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        out += &format!("    {line}\n");
+    }
+
+    out += "};\n";
+    out
+}
+
+/// Format bytes as space-separated hex.
+fn format_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The background color of the stack in use.
+const STACK_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(0, 40, 60, 40);
+
+/// The color of a non-zero byte at `addr`, tinted by the segment it lies in.
+fn segment_color(addr: usize) -> Option<egui::Color32> {
+    if (ADDR_TEXT..ADDR_STATIC).contains(&addr) {
+        Some(egui::Color32::GOLD)
+    } else if (ADDR_STATIC..ADDR_HEAP).contains(&addr) {
+        Some(egui::Color32::LIGHT_BLUE)
+    } else {
+        None
     }
 }
 
@@ -169,10 +662,10 @@ impl ComputerMut<&[u8], LayoutJob> for ChunkAscii {
         for &byte in key {
             match byte {
                 32..=126 => buf.push(byte as char),
-                b'\n' => {
+                _ => {
                     add_buf(&mut job, &mut buf);
                     job.append(
-                        "↵",
+                        if byte == b'\n' { "↵" } else { "·" },
                         0.0,
                         egui::TextFormat {
                             color: egui::Color32::DARK_GRAY,
@@ -181,7 +674,6 @@ impl ComputerMut<&[u8], LayoutJob> for ChunkAscii {
                         },
                     );
                 }
-                _ => buf.push(' '),
             }
         }
 
@@ -1,29 +1,38 @@
+use std::time::Duration;
+
 use crate::simulator::Registers;
 
-use self::{editor::Editor, output::OutputTab};
+use self::{editor::Editor, encoding::EncodingTab, output::OutputTab};
 
 use super::App;
 
+pub mod console;
 pub mod editor;
+pub mod encoding;
 pub mod memory;
 pub mod output;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum AppTab {
-    Editor,
+    /// An editor, by index into the app's editors.
+    Editor(usize),
     Memory,
     Log,
     Io,
     Registers,
+    Encoding,
+    Console,
 }
 
 #[allow(dead_code)]
 pub static TABS_LIST: &[AppTab] = &[
-    AppTab::Editor,
+    AppTab::Editor(0),
     AppTab::Memory,
     AppTab::Log,
     AppTab::Io,
     AppTab::Registers,
+    AppTab::Encoding,
+    AppTab::Console,
 ];
 
 impl egui_dock::TabViewer for App {
@@ -31,22 +40,34 @@ impl egui_dock::TabViewer for App {
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
         match tab {
-            AppTab::Editor => "Editor",
+            AppTab::Editor(index) => return Editor::title(self, *index).into(),
             AppTab::Memory => "Memory",
             AppTab::Log => "Log",
             AppTab::Io => "Program I/O",
             AppTab::Registers => "Registers",
+            AppTab::Encoding => "Encoding",
+            AppTab::Console => "Console",
         }
         .into()
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
         match tab {
-            AppTab::Editor => Editor::show(self, ui),
-            AppTab::Memory => self.memory.show(ui, &self.proc.mem),
+            AppTab::Editor(index) => Editor::show(self, ui, *index),
+            AppTab::Memory => self.memory.show(
+                ui,
+                &mut self.proc,
+                &self.proc_tx,
+                Duration::from_millis(self.settings.memory_refresh_ms),
+            ),
             AppTab::Log => self.output.show(OutputTab::Log, ui, &self.proc_tx),
             AppTab::Io => self.output.show(OutputTab::Io, ui, &self.proc_tx),
             AppTab::Registers => Registers::show(self, ui),
+            AppTab::Encoding => EncodingTab::show(self, ui),
+            AppTab::Console => {
+                self.console
+                    .show(ui, &self.proc, &self.proc_tx, &self.settings.assemble)
+            }
         }
     }
 }
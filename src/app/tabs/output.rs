@@ -1,6 +1,7 @@
 use egui::TextStyle;
 
 use crate::{
+    app::settings::DEFAULT_LOG_LIMIT,
     simulator::{Io, ProcMessage, ProcTx},
     util::ParBuf,
 };
@@ -24,12 +25,52 @@ impl Default for Output {
         Self {
             tab: OutputTab::Log,
             io: Io::new(),
-            log: ParBuf::new().limit(100),
+            log: ParBuf::new().limit(DEFAULT_LOG_LIMIT),
         }
     }
 }
 
 impl Output {
+    /// Send the keystrokes typed into the input field straight to the
+    /// processor, echoing them, while it waits on a character read. Enter
+    /// sends `\n`; Backspace is dropped, since a character sent can't be
+    /// taken back.
+    fn send_keys(&mut self, ui: &mut egui::Ui, id: egui::Id, proc_tx: &ProcTx) {
+        if !ui.memory(|m| m.has_focus(id)) {
+            return;
+        }
+
+        let mut keys = String::new();
+        ui.input_mut(|input| {
+            input.events.retain(|event| match event {
+                egui::Event::Text(text) => {
+                    keys.push_str(text);
+                    false
+                }
+                egui::Event::Key {
+                    key: egui::Key::Enter,
+                    pressed,
+                    ..
+                } => {
+                    if *pressed {
+                        keys.push('\n');
+                    }
+                    false
+                }
+                egui::Event::Key {
+                    key: egui::Key::Backspace,
+                    ..
+                } => false,
+                _ => true,
+            })
+        });
+
+        for c in keys.chars() {
+            let _ = proc_tx.send(ProcMessage::Key(c));
+        }
+        self.io.add(keys);
+    }
+
     pub fn show(&mut self, tab: OutputTab, ui: &mut egui::Ui, proc_tx: &ProcTx) {
         egui::ScrollArea::vertical()
             .auto_shrink([false, false])
@@ -40,17 +81,29 @@ impl Output {
                         ui.monospace(line);
                     }
 
+                    // the input sits right after any prompt on the pending
+                    // line, and is echoed onto it when submitted
                     ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 0.0;
+
                         if !self.io.buf.is_empty() {
                             ui.monospace(&self.io.buf);
                         }
-                    });
 
-                    {
+                        let id = egui::Id::new("output_io_input");
+                        if self.io.awaiting_char {
+                            self.send_keys(ui, id, proc_tx);
+                        }
+
                         let input = egui::TextEdit::singleline(&mut self.io.in_buf)
+                            .id(id)
                             .font(TextStyle::Monospace)
-                            .desired_width(f32::INFINITY);
-                        // .frame(false);
+                            .desired_width(f32::INFINITY)
+                            .hint_text(if self.io.awaiting_char {
+                                "Press a key..."
+                            } else {
+                                ""
+                            });
                         let data = input.show(ui);
                         if data.response.lost_focus()
                             && data.response.ctx.input(|i| i.key_down(egui::Key::Enter))
@@ -60,9 +113,13 @@ impl Output {
                             self.io.add(format!("{string}\n"));
                             let _ = proc_tx.send(ProcMessage::Io(string));
                         }
-                    }
+                    });
                 }
                 OutputTab::Log => {
+                    if self.log.dropped() > 0 {
+                        ui.weak(format!("({} earlier lines hidden)", self.log.dropped()));
+                    }
+
                     for line in self.log.iter() {
                         ui.monospace(line);
                     }
@@ -1,7 +1,7 @@
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
-use crate::app::tabs::editor::LexemeHint;
+use crate::app::{highlighting::HighlightTheme, tabs::editor::LexemeHint};
 
 #[derive(Debug, Clone)]
 pub struct Directive {
@@ -10,7 +10,7 @@ pub struct Directive {
 }
 
 impl LexemeHint for Directive {
-    fn show(&self, ui: &mut egui::Ui) {
+    fn show(&self, ui: &mut egui::Ui, _theme: &HighlightTheme) {
         ui.label(egui::RichText::new(self.name).monospace().strong());
         ui.label(self.desc);
     }
@@ -38,7 +38,10 @@ directives! {
     ".byte":    "Writes a literal byte to the binary.",
     ".half":    "Writes a literal 16-bit integer to the binary.",
     ".word":    "Writes a literal 32-bit integer to the binary.",
+    ".float":   "Writes a literal single-precision float to the binary.",
+    ".double":  "Writes a literal double-precision float to the binary.",
     ".asciiz":  "Writes a string followed by a nul terminator to the binary.",
     ".align":   "Aligns the writer to the nearest 2^n-th byte, where n is the number given.",
     ".stringz": "Shorthand for .asciiz STRING .align 2.",
+    ".set":     "Sets an assembler option. .set noat forbids expanding instructions through $at, and .set at allows it again.",
 }
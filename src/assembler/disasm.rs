@@ -0,0 +1,108 @@
+use crate::simulator::Registers;
+
+use super::inst::{Inst, InstArg, InstType, INST_OPCODE_FUNC, OPCODE_COP0};
+
+/// The bit fields of an encoded word. Every field is extracted regardless of
+/// the instruction type; which ones are meaningful depends on the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub opcode: u8,
+    pub rs: u8,
+    pub rt: u8,
+    pub rd: u8,
+    pub shamt: u8,
+    pub func: u8,
+    pub imm: u16,
+    pub addr: u32,
+}
+
+impl DecodedInstruction {
+    /// The fields of the word's encoding format, from the most significant
+    /// bits down, as name, value and width in bits.
+    pub fn layout(&self) -> Vec<(&'static str, u32, u32)> {
+        let mut fields = vec![("opcode", self.opcode as u32, 6)];
+
+        match self.opcode {
+            0x00 | OPCODE_COP0 => fields.extend([
+                ("rs", self.rs as u32, 5),
+                ("rt", self.rt as u32, 5),
+                ("rd", self.rd as u32, 5),
+                ("shamt", self.shamt as u32, 5),
+                ("func", self.func as u32, 6),
+            ]),
+            0x02 | 0x03 => fields.push(("addr", self.addr, 26)),
+            _ => fields.extend([
+                ("rs", self.rs as u32, 5),
+                ("rt", self.rt as u32, 5),
+                ("imm", self.imm as u32, 16),
+            ]),
+        }
+
+        fields
+    }
+}
+
+/// Split an encoded word into its bit fields.
+pub fn decode_fields(word: u32) -> DecodedInstruction {
+    DecodedInstruction {
+        opcode: (word >> 26) as u8,
+        rs: ((word >> 21) & 0x1f) as u8,
+        rt: ((word >> 16) & 0x1f) as u8,
+        rd: ((word >> 11) & 0x1f) as u8,
+        shamt: ((word >> 6) & 0x1f) as u8,
+        func: (word & 0x3f) as u8,
+        imm: (word & 0xffff) as u16,
+        addr: word & 0x3ffffff,
+    }
+}
+
+/// Decode the instruction of an encoded word, if it is a known instruction.
+pub fn decode(word: u32) -> Option<&'static Inst> {
+    decode_inst(&decode_fields(word))
+}
+
+/// Look up the instruction of a word's fields, if it is a known instruction.
+pub fn decode_inst(fields: &DecodedInstruction) -> Option<&'static Inst> {
+    let func = match fields.opcode {
+        0x00 => fields.func,
+        OPCODE_COP0 => fields.rs,
+        _ => 0x00,
+    };
+
+    INST_OPCODE_FUNC.get(&(fields.opcode, func)).copied()
+}
+
+/// Disassemble an encoded word, e.g. into `add $t0, $t1, $t2`.
+pub fn disassemble(word: u32) -> Option<String> {
+    let fields = decode_fields(word);
+    let inst = decode_inst(&fields)?;
+
+    let rs = fields.rs as usize;
+    let rt = fields.rt as usize;
+    let rd = fields.rd as usize;
+    let shamt = fields.shamt;
+    let imm = fields.imm;
+
+    let args = inst
+        .args
+        .iter()
+        .take_while(|arg| !matches!(arg, InstArg::None))
+        .map(|arg| match arg {
+            InstArg::Rs => format!("${}", Registers::name(rs)),
+            InstArg::Rt => format!("${}", Registers::name(rt)),
+            InstArg::Rd if inst.opcode == OPCODE_COP0 => format!("${rd}"),
+            InstArg::Rd => format!("${}", Registers::name(rd)),
+            InstArg::Shamt => shamt.to_string(),
+            InstArg::SImm => (imm as i16).to_string(),
+            InstArg::UImm => format!("0x{imm:x}"),
+            InstArg::Addr => format!("0x{:08x}", fields.addr << 2),
+            InstArg::Word | InstArg::None => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    Some(match (inst.ty, args.as_slice()) {
+        (InstType::Ils, [rt, offset, rs]) => format!("{} {rt}, {offset}({rs})", inst.mnemonic),
+        (_, []) => inst.mnemonic.to_string(),
+        _ => format!("{} {}", inst.mnemonic, args.join(", ")),
+    })
+}
@@ -0,0 +1,43 @@
+/// An immediate expression, e.g. `4*2` or `ARRAY+8`.
+#[derive(Debug, Clone)]
+pub enum Expr<'a> {
+    /// A literal integer.
+    Lit(i64),
+
+    /// A label reference. Evaluates to its address.
+    Label(&'a str),
+
+    /// A negated expression.
+    Neg(Box<Expr<'a>>),
+
+    /// The sum of two expressions.
+    Add(Box<Expr<'a>>, Box<Expr<'a>>),
+
+    /// The difference of two expressions.
+    Sub(Box<Expr<'a>>, Box<Expr<'a>>),
+
+    /// The product of two expressions.
+    Mul(Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+impl<'a> Expr<'a> {
+    /// Evaluate the expression, resolving labels with `resolve`.
+    pub fn eval<E, F>(&self, resolve: &mut F) -> Result<i64, E>
+    where
+        F: FnMut(&'a str) -> Result<i64, E>,
+    {
+        Ok(match self {
+            Self::Lit(value) => *value,
+            Self::Label(label) => resolve(label)?,
+            Self::Neg(expr) => expr.eval(resolve)?.wrapping_neg(),
+            Self::Add(lhs, rhs) => lhs.eval(resolve)?.wrapping_add(rhs.eval(resolve)?),
+            Self::Sub(lhs, rhs) => lhs.eval(resolve)?.wrapping_sub(rhs.eval(resolve)?),
+            Self::Mul(lhs, rhs) => lhs.eval(resolve)?.wrapping_mul(rhs.eval(resolve)?),
+        })
+    }
+
+    /// Evaluate the expression if it does not reference any labels.
+    pub fn eval_const(&self) -> Option<i64> {
+        self.eval(&mut |_| Err(())).ok()
+    }
+}
@@ -0,0 +1,174 @@
+use super::lexer::{Lexeme, LexemeKind, Lexer};
+
+/// The column instructions and directives start at.
+const INST_COLUMN: usize = 8;
+
+/// The column operands start at.
+const OPERAND_COLUMN: usize = 16;
+
+/// The column trailing comments start at.
+const COMMENT_COLUMN: usize = 40;
+
+/// Pad `line` with spaces up to `column`, or with a single space if it is
+/// already past it. Empty lines are padded without the extra space.
+fn pad_to(line: &mut String, column: usize) {
+    let len = line.chars().count();
+    if len < column {
+        line.push_str(&" ".repeat(column - len));
+    } else if len > 0 {
+        line.push(' ');
+    }
+}
+
+/// Join the operand lexemes of a line, normalizing the spacing after commas
+/// and collapsing any other whitespace between lexemes to a single space
+/// (or none, inside parentheses).
+fn format_operands(source: &str, lexemes: &[&Lexeme]) -> String {
+    let mut operands = String::new();
+
+    for (i, lexeme) in lexemes.iter().enumerate() {
+        let text = &source[lexeme.slice.clone()];
+
+        if let Some(prev) = i.checked_sub(1).map(|i| lexemes[i]) {
+            let prev_text = &source[prev.slice.clone()];
+            let after_comma = prev_text == ",";
+            let spaced = !matches!(text, "," | ")")
+                && prev_text != "("
+                && prev.slice.end < lexeme.slice.start;
+            if after_comma || spaced {
+                operands.push(' ');
+            }
+        }
+
+        operands.push_str(text);
+    }
+
+    operands
+}
+
+/// Reformat assembly source into aligned columns.
+///
+/// Labels are placed at column 0, instructions and directives at a fixed
+/// column with their operands aligned after them, and trailing comments are
+/// aligned to the right. Blank lines and comment-only lines are preserved.
+pub fn format_source(source: &str) -> String {
+    let lexemes = Lexer::new(source).with_comments(true).lex();
+
+    let mut lines = vec![vec![]; source.split('\n').count()];
+    for lexeme in lexemes.iter() {
+        if let Some(line) = lines.get_mut(lexeme.line as usize) {
+            line.push(lexeme);
+        }
+    }
+
+    let formatted = lines
+        .iter()
+        .zip(source.split('\n'))
+        .map(|(lexemes, original)| {
+            let (comment, mut rest) = match lexemes.split_last() {
+                Some((last, rest)) if last.kind == LexemeKind::Comment => {
+                    (Some(&source[last.slice.clone()]), rest)
+                }
+                _ => (None, lexemes.as_slice()),
+            };
+
+            let mut line = String::new();
+
+            // label definitions at column 0
+            while let Some((first, tail)) = rest.split_first() {
+                let text = &source[first.slice.clone()];
+                if first.kind != LexemeKind::Label || !text.ends_with(':') {
+                    break;
+                }
+
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(text);
+                rest = tail;
+            }
+
+            // the instruction or directive, followed by its operands
+            if let Some((first, operands)) = rest.split_first() {
+                pad_to(&mut line, INST_COLUMN);
+                line.push_str(&source[first.slice.clone()]);
+
+                if !operands.is_empty() {
+                    pad_to(&mut line, OPERAND_COLUMN);
+                    line.push_str(&format_operands(source, operands));
+                }
+            }
+
+            if let Some(comment) = comment {
+                if !line.is_empty() {
+                    pad_to(&mut line, COMMENT_COLUMN);
+                } else if original.starts_with(char::is_whitespace) {
+                    // indented comment-only lines follow the instructions
+                    pad_to(&mut line, INST_COLUMN);
+                }
+                line.push_str(comment);
+            }
+
+            line
+        })
+        .collect::<Vec<_>>();
+
+    // keep CRLF line endings if the source uses them
+    let newline = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    formatted.join(newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_columns() {
+        assert_eq!(
+            format_source("main:  addi   $t0,$t0 ,  1 # bump\n  j main"),
+            concat!(
+                "main:   addi    $t0, $t0, 1             # bump\n",
+                "        j       main",
+            )
+        );
+    }
+
+    #[test]
+    fn load_store_operands() {
+        assert_eq!(
+            format_source("lw $t0 , -4( $sp )"),
+            "        lw      $t0, -4($sp)"
+        );
+    }
+
+    #[test]
+    fn keeps_blank_lines_and_comments() {
+        assert_eq!(
+            format_source("# header\n\n.data\nmsg: .asciiz \"hi\"\n    ; note\n"),
+            concat!(
+                "# header\n",
+                "\n",
+                "        .data\n",
+                "msg:    .asciiz \"hi\"\n",
+                "        ; note\n",
+            )
+        );
+    }
+
+    #[test]
+    fn long_labels() {
+        assert_eq!(
+            format_source("a_long_label: nop\nfoo: bar:"),
+            "a_long_label: nop\nfoo: bar:"
+        );
+    }
+
+    #[test]
+    fn keeps_crlf() {
+        assert_eq!(format_source("nop\r\nnop"), "        nop\r\n        nop");
+    }
+}
@@ -2,8 +2,9 @@ use std::{collections::HashMap, str::FromStr};
 
 use egui::{text::LayoutJob, Color32, TextFormat};
 use lazy_static::lazy_static;
+use thiserror::Error;
 
-use crate::app::tabs::editor::LexemeHint;
+use crate::app::{highlighting::HighlightTheme, tabs::editor::LexemeHint};
 
 use super::lexer::{LexemeKind, Lexer};
 
@@ -27,7 +28,8 @@ pub struct Inst {
     /// The instruction opcode.
     pub opcode: u8,
 
-    /// The instruction func value, if the instruction is R-type.
+    /// The instruction func value, if the instruction is R-type. For
+    /// coprocessor 0 instructions, the operation in the rs field.
     pub func: u8,
 }
 
@@ -40,7 +42,7 @@ pub struct PseudoInst {
 }
 
 impl LexemeHint for Inst {
-    fn show(&self, ui: &mut egui::Ui) {
+    fn show(&self, ui: &mut egui::Ui, theme: &HighlightTheme) {
         let ty_ils = matches!(self.ty, InstType::Ils);
 
         ui.horizontal(|ui| {
@@ -49,7 +51,7 @@ impl LexemeHint for Inst {
             let mut usage_job = LayoutJob::default();
 
             // mnemonic
-            usage_job.append(self.mnemonic, 0.0, LexemeKind::Inst.into_text_format());
+            usage_job.append(self.mnemonic, 0.0, LexemeKind::Inst.into_text_format(theme));
 
             // arguments
             for (i, arg) in self.args.iter().enumerate() {
@@ -59,12 +61,16 @@ impl LexemeHint for Inst {
 
                 if ty_ils {
                     match i {
-                        1 => usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format()),
-                        2 => usage_job.append("(", 0.0, LexemeKind::Punct.into_text_format()),
+                        1 => usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format(theme)),
+                        2 => usage_job.append(
+                            "(",
+                            0.0,
+                            LexemeKind::Punct.text_format_for("(", theme),
+                        ),
                         _ => (),
                     }
                 } else if i > 0 {
-                    usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format());
+                    usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format(theme));
                 }
 
                 usage_job.append(
@@ -79,7 +85,7 @@ impl LexemeHint for Inst {
             }
 
             if ty_ils {
-                usage_job.append(")", 0.0, LexemeKind::Punct.into_text_format());
+                usage_job.append(")", 0.0, LexemeKind::Punct.text_format_for(")", theme));
             }
 
             ui.label(usage_job);
@@ -120,7 +126,7 @@ impl LexemeHint for Inst {
 }
 
 impl LexemeHint for PseudoInst {
-    fn show(&self, ui: &mut egui::Ui) {
+    fn show(&self, ui: &mut egui::Ui, theme: &HighlightTheme) {
         ui.horizontal(|ui| {
             ui.strong(self.name);
             ui.label("(pseudo)");
@@ -128,7 +134,7 @@ impl LexemeHint for PseudoInst {
             let mut usage_job = LayoutJob::default();
 
             // mnemonic
-            usage_job.append(self.mnemonic, 0.0, LexemeKind::Inst.into_text_format());
+            usage_job.append(self.mnemonic, 0.0, LexemeKind::Inst.into_text_format(theme));
 
             // arguments
             for (i, arg) in self.args.iter().enumerate() {
@@ -137,7 +143,7 @@ impl LexemeHint for PseudoInst {
                 }
 
                 if i > 0 {
-                    usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format());
+                    usage_job.append(", ", 0.0, LexemeKind::Punct.into_text_format(theme));
                 }
 
                 usage_job.append(
@@ -188,6 +194,89 @@ impl LexemeHint for PseudoInst {
     }
 }
 
+/// A call of a real instruction with its operands, ready to be encoded.
+#[derive(Debug, Clone, Copy)]
+pub struct InstCall {
+    pub inst: &'static Inst,
+    pub rs: u8,
+    pub rt: u8,
+    pub rd: u8,
+    pub shamt: u8,
+
+    /// The immediate of an I-type instruction, or the word address of a
+    /// J-type instruction.
+    pub imm: u32,
+}
+
+/// An error encoding an instruction call.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    #[error("{mnemonic}: {field} 0x{value:x} does not fit in {bits} bits")]
+    FieldOverflow {
+        mnemonic: &'static str,
+        field: &'static str,
+        value: u32,
+        bits: u32,
+    },
+}
+
+impl InstCall {
+    /// Encode the call into an instruction word. Errors if a field does not
+    /// fit in its bits, rather than letting it corrupt its neighbours.
+    pub fn encode(&self) -> Result<u32, EncodeError> {
+        let fields: &[(&'static str, u32, u32)] = match self.inst.ty {
+            InstType::R => &[
+                ("rs", self.rs as u32, 5),
+                ("rt", self.rt as u32, 5),
+                ("rd", self.rd as u32, 5),
+                ("shamt", self.shamt as u32, 5),
+            ],
+            InstType::I | InstType::Ils => &[
+                ("rs", self.rs as u32, 5),
+                ("rt", self.rt as u32, 5),
+                ("imm", self.imm, 16),
+            ],
+            InstType::J => &[("addr", self.imm, 26)],
+        };
+
+        for &(field, value, bits) in fields {
+            if value >> bits != 0 {
+                return Err(EncodeError::FieldOverflow {
+                    mnemonic: self.inst.mnemonic,
+                    field,
+                    value,
+                    bits,
+                });
+            }
+        }
+
+        let opcode = (self.inst.opcode as u32) << 26;
+
+        Ok(match self.inst.ty {
+            InstType::R => {
+                // coprocessor 0 instructions select their operation in the rs
+                // field, except eret, which sets the CO bit and its own func
+                let (rs, func) = match (self.inst.opcode, self.inst.func) {
+                    (OPCODE_COP0, 0x10) => (0x10, 0x18),
+                    (OPCODE_COP0, op) => (op, 0),
+                    _ => (self.rs, self.inst.func),
+                };
+
+                opcode
+                    | ((rs as u32) << 21)
+                    | ((self.rt as u32) << 16)
+                    | ((self.rd as u32) << 11)
+                    | ((self.shamt as u32) << 6)
+                    | func as u32
+            }
+            InstType::I | InstType::Ils => {
+                opcode | ((self.rs as u32) << 21) | ((self.rt as u32) << 16) | self.imm
+            }
+            InstType::J => opcode | self.imm,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InstType {
     /// R-type.
@@ -319,10 +408,19 @@ macro_rules! pseudo_instructions {
     }
 }
 
+/// The opcode of coprocessor 0 instructions, which select their operation
+/// in the rs field rather than the func field.
+pub const OPCODE_COP0: u8 = 0x10;
+
 /// Instruction mnemonics that store addresses as relative to their
 /// address, NOT absolutely.
 pub static INST_ADDR_RELATIVE: &[&str] = &["beq", "bne"];
 
+/// Logical immediate instructions, and the register-register instruction
+/// they expand into (through `$at`) when given an immediate that does not fit
+/// in 16 bits.
+pub static INST_LARGE_IMM: &[(&str, &str)] = &[("andi", "and"), ("ori", "or"), ("xori", "xor")];
+
 instructions! {
     // mnem. name                               (T, Opco/Func): description => [Arg1, Arg2, Arg3],
     "add"    "Add"                              (R, 0x00/0x20): "Performs $rd = $rs + $rt." => [Rd, Rs, Rt],
@@ -330,11 +428,17 @@ instructions! {
     "addiu"  "Add Immediate Unsigned"           (I, 0x09/0x00): "Performs $rt = $rs + $imm, unsigned." => [Rt, Rs, UImm],
     "addu"   "Add Unsigned"                     (R, 0x00/0x21): "Performs $rd = $rs + $rt, unsigned." => [Rd, Rs, Rt],
     "and"    "AND"                              (R, 0x00/0x24): "Performs $rd = $rs & $rt." => [Rd, Rs, Rt],
-    "andi"   "AND Immediate"                    (I, 0x0c/0x00): "Performs $rt = $rs & $imm." => [Rt, Rs, SImm],
+    "andi"   "AND Immediate"                    (I, 0x0c/0x00): "Performs $rt = $rs & $imm. Wider immediates expand through $at." => [Rt, Rs, SImm],
+    "div"    "Divide"                           (R, 0x00/0x1a): "Divides $rs by $rt, storing the quotient in LO and the remainder in HI. Dividing by zero leaves them unchanged." => [Rs, Rt, None],
+    "divu"   "Divide Unsigned"                  (R, 0x00/0x1b): "Divides $rs by $rt, unsigned, storing the quotient in LO and the remainder in HI. Dividing by zero leaves them unchanged." => [Rs, Rt, None],
     "lui"    "Load Upper Immediate"             (I, 0x0f/0x00): "Performs $rt = $imm << 16." => [Rt, UImm, None],
-    "nor"    "NOR"                              (R, 0x00/0x27): "Not OR. Performs $rd = ~($rs | $rt)." => [Rs, Rt, Rd],
+    "mfhi"   "Move From HI"                     (R, 0x00/0x10): "Copies the high word of the last multiplication, or the remainder of the last division, into $rd." => [Rd, None, None],
+    "mflo"   "Move From LO"                     (R, 0x00/0x12): "Copies the low word of the last multiplication, or the quotient of the last division, into $rd." => [Rd, None, None],
+    "mult"   "Multiply"                         (R, 0x00/0x18): "Multiplies $rs by $rt, storing the 64-bit product in HI and LO." => [Rs, Rt, None],
+    "multu"  "Multiply Unsigned"                (R, 0x00/0x19): "Multiplies $rs by $rt, unsigned, storing the 64-bit product in HI and LO." => [Rs, Rt, None],
+    "nor"    "NOR"                              (R, 0x00/0x27): "Not OR. Performs $rd = ~($rs | $rt)." => [Rd, Rs, Rt],
     "or"     "OR"                               (R, 0x00/0x25): "Performs $rd = $rs | $rt." => [Rd, Rs, Rt],
-    "ori"    "OR Immediate"                     (I, 0x0d/0x00): "Performs $rt = $rs | $imm." => [Rt, Rs, SImm],
+    "ori"    "OR Immediate"                     (I, 0x0d/0x00): "Performs $rt = $rs | $imm. Wider immediates expand through $at." => [Rt, Rs, SImm],
     "slt"    "Set Less Than"                    (R, 0x00/0x2a): "Performs $rd = $rs < $rt." => [Rd, Rs, Rt],
     "slti"   "Set Less Than Immediate"          (I, 0x0a/0x00): "Performs $rt = $rs < $imm." => [Rt, Rs, SImm],
     "sltiu"  "Set Less Than Immediate Unsigned" (I, 0x0b/0x00): "Performs $rt = $rs < $imm, unsigned." => [Rt, Rs, UImm],
@@ -342,9 +446,13 @@ instructions! {
     "sll"    "Shift Left Logical"               (R, 0x00/0x00): "Performs $rd = $rt << $shamt." => [Rd, Rt, Shamt],
     "sra"    "Shift Right Arithmetic"           (R, 0x00/0x03): "Performs $rd = $rt >> $shamt." => [Rd, Rt, Shamt],
     "srl"    "Shift Right Logical"              (R, 0x00/0x02): "Performs $rd = $rt >> $shamt." => [Rd, Rt, Shamt],
+    "sllv"   "Shift Left Logical Variable"      (R, 0x00/0x04): "Performs $rd = $rt << $rs." => [Rd, Rt, Rs],
+    "srav"   "Shift Right Arithmetic Variable"  (R, 0x00/0x07): "Performs $rd = $rt >> $rs." => [Rd, Rt, Rs],
+    "srlv"   "Shift Right Logical Variable"     (R, 0x00/0x06): "Performs $rd = $rt >> $rs." => [Rd, Rt, Rs],
     "sub"    "Subtract"                         (R, 0x00/0x22): "Performs $rd = $rs - $rt." => [Rd, Rs, Rt],
-    "subu"   "Subtract Unsigned"                (R, 0x23/0x00): "Performs $rd = $rs - $rt, unsigned." => [Rd, Rs, Rt],
+    "subu"   "Subtract Unsigned"                (R, 0x00/0x23): "Performs $rd = $rs - $rt, unsigned." => [Rd, Rs, Rt],
     "xor"    "XOR"                              (R, 0x00/0x26): "Performs $rd = $rs ^ $rt." => [Rd, Rs, Rt],
+    "xori"   "XOR Immediate"                    (I, 0x0e/0x00): "Performs $rt = $rs ^ $imm, zero-extended. Wider immediates expand through $at." => [Rt, Rs, UImm],
 
     "lbu"    "Load Byte Unsigned"               (Ils, 0x24/0x00): "Loads $mem($rs + $imm) into $rt." => [Rt, SImm, Rs],
     "lhu"    "Load Half Unsigned"               (Ils, 0x25/0x00): "Loads two bytes at $mem($rs + $imm) into $rt." => [Rt, SImm, Rs],
@@ -359,6 +467,9 @@ instructions! {
     "jal"    "Jump and Link"                    (J, 0x03/0x00): "Set $ra to $pc, then jump to $addr." => [Addr, None, None],
     "jr"     "Jump Register"                    (R, 0x00/0x08): "Jump to the address specified by $rs." => [Rs, None, None],
     "syscall" "System Call"                     (R, 0x00/0x0c): "Perform a system call." => [None, None, None],
+    "mfc0"   "Move From Coprocessor 0"          (R, 0x10/0x00): "Copies coprocessor 0 register $rd into $rt." => [Rt, Rd, None],
+    "mtc0"   "Move To Coprocessor 0"            (R, 0x10/0x04): "Copies $rt into coprocessor 0 register $rd." => [Rt, Rd, None],
+    "eret"   "Exception Return"                 (R, 0x10/0x10): "Jump to the address in EPC, returning from an exception handler." => [None, None, None],
 }
 
 pseudo_instructions! {
@@ -366,4 +477,112 @@ pseudo_instructions! {
     "nop"   "No Operation": "Does nothing. Expands to a blank call to sll." => [None, None, None],
     "li"    "Load Immediate": "Loads $imm into $rt." => [Rt, Word, None],
     "move"  "Move": "Copies $rs into $rt." => [Rt, Rs, None],
+    "not"   "NOT": "Performs $rd = ~$rs. Expands to nor $rd, $rs, $zero." => [Rd, Rs, None],
+    "neg"   "Negate": "Performs $rd = -$rs. Expands to sub $rd, $zero, $rs." => [Rd, Rs, None],
+    "abs"   "Absolute Value": "Performs $rd = |$rs|. Expands through $at to sra, xor and subu, so the most negative value is returned unchanged instead of overflowing." => [Rd, Rs, None],
+    "mul"   "Multiply": "Performs $rd = $rs * $rt, keeping the low word. Expands to mult and mflo." => [Rd, Rs, Rt],
+    "mulo"  "Multiply with Overflow": "Performs $rd = $rs * $rt, raising an overflow exception if the product does not fit in a word. Expands through $at to mult, mfhi, mflo, sra and beq, overflowing with lui and add." => [Rd, Rs, Rt],
+    "mulou" "Multiply Unsigned with Overflow": "Performs $rd = $rs * $rt, unsigned, raising an overflow exception if the product does not fit in a word. Expands through $at to multu, mfhi and beq, overflowing with lui and add." => [Rd, Rs, Rt],
+    "rem"   "Remainder": "Performs $rd = $rs % $rt, taking the sign of $rs. Expands to div and mfhi." => [Rd, Rs, Rt],
+    "remu"  "Remainder Unsigned": "Performs $rd = $rs % $rt, unsigned. Expands to divu and mfhi." => [Rd, Rs, Rt],
+    "b"     "Branch": "Branch to $addr, relative to the next instruction. Expands to beq $zero, $zero, $addr." => [Addr, None, None],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(mnemonic: &str) -> InstCall {
+        InstCall {
+            inst: INST_MNEMONICS[mnemonic],
+            rs: 0,
+            rt: 0,
+            rd: 0,
+            shamt: 0,
+            imm: 0,
+        }
+    }
+
+    #[test]
+    fn encode_fits() {
+        let add = InstCall {
+            rs: 9,
+            rt: 10,
+            rd: 8,
+            ..call("add")
+        };
+        assert_eq!(add.encode(), Ok(0x012a4020));
+
+        let addi = InstCall {
+            rs: 8,
+            rt: 8,
+            imm: 0xffff,
+            ..call("addi")
+        };
+        assert_eq!(addi.encode(), Ok(0x2108ffff));
+
+        let j = InstCall {
+            imm: 0x3ffffff,
+            ..call("j")
+        };
+        assert_eq!(j.encode(), Ok(0x0bffffff));
+    }
+
+    #[test]
+    fn encode_rejects_oversized_fields() {
+        let overflow = |call: InstCall, field: &str| match call.encode() {
+            Err(EncodeError::FieldOverflow { field: f, .. }) => assert_eq!(f, field),
+            other => panic!("expected {field} to overflow, got {other:?}"),
+        };
+
+        overflow(
+            InstCall {
+                rs: 32,
+                ..call("add")
+            },
+            "rs",
+        );
+        overflow(
+            InstCall {
+                rt: 32,
+                ..call("add")
+            },
+            "rt",
+        );
+        overflow(
+            InstCall {
+                rd: 32,
+                ..call("add")
+            },
+            "rd",
+        );
+        overflow(
+            InstCall {
+                shamt: 32,
+                ..call("sll")
+            },
+            "shamt",
+        );
+        overflow(
+            InstCall {
+                imm: 0x10000,
+                ..call("addi")
+            },
+            "imm",
+        );
+        overflow(
+            InstCall {
+                rt: 32,
+                ..call("lw")
+            },
+            "rt",
+        );
+        overflow(
+            InstCall {
+                imm: 0x4000000,
+                ..call("j")
+            },
+            "addr",
+        );
+    }
 }
@@ -37,7 +37,14 @@ pub enum LexemeKind {
     Whitespace,
 }
 
+/// Whether `c` is an operator or grouping character, which is always lexed
+/// on its own.
+fn is_operator(c: char) -> bool {
+    matches!(c, '(' | ')' | '+' | '-' | '*' | ',')
+}
+
 pub struct Lexer<'a> {
+    text: &'a str,
     chars: IndexedChars<'a>,
     whitespace: bool,
     comments: bool,
@@ -46,6 +53,7 @@ pub struct Lexer<'a> {
 impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Self {
         Self {
+            text,
             chars: IndexedChars::new(text),
             whitespace: false,
             comments: false,
@@ -83,6 +91,43 @@ impl<'a> Lexer<'a> {
         res
     }
 
+    /// Take the rest of a decimal number starting at `base`, including an
+    /// optional fractional part and exponent, e.g. `3.14` or `6.02e23`.
+    fn take_decimal(&mut self, base: usize) -> Range<usize> {
+        let starts_with_digit = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let mut slice = self.take_while(base, char::is_numeric);
+
+        // a single `.` is only part of the number if digits follow it, so
+        // that sections like `.text` are left alone
+        let rest = &self.text[slice.end..];
+        if rest.starts_with('.') && starts_with_digit(&rest[1..]) {
+            self.chars.next();
+            slice = self.take_while(base, char::is_numeric);
+
+            let exp = self.text[slice.end..]
+                .strip_prefix(|c| c == 'e' || c == 'E')
+                .map(|rest| rest.strip_prefix(|c| c == '+' || c == '-').unwrap_or(rest));
+
+            if matches!(exp, Some(digits) if starts_with_digit(digits)) {
+                self.chars.next();
+                if self.peek_is(|c| c == '+' || c == '-') {
+                    self.chars.next();
+                }
+                slice = self.take_while(base, char::is_numeric);
+            }
+        }
+
+        slice
+    }
+
+    /// Take the rest of a hexadecimal number starting at `base`, after its
+    /// `0x` prefix.
+    fn take_hex(&mut self, base: usize) -> Range<usize> {
+        self.take_while(base, |ref c| {
+            c.is_numeric() || ('a'..='f').contains(c) || ('A'..='F').contains(c)
+        })
+    }
+
     fn append_or_add_lexeme(
         &mut self,
         lexemes: &mut Vec<Lexeme>,
@@ -95,7 +140,7 @@ impl<'a> Lexer<'a> {
                 kind: top_kind,
                 ref mut slice,
                 ..
-            }) if *top_kind == kind => {
+            }) if *top_kind == kind && !self.text[slice.clone()].starts_with(is_operator) => {
                 slice.end = self.chars.peek_boundary();
             }
             _ => {
@@ -117,7 +162,8 @@ impl<'a> Lexer<'a> {
             match c {
                 // comments
                 ';' | '#' => {
-                    let slice = self.take_while(idx, |c| c != '\n');
+                    // stop before a CRLF line ending, too
+                    let slice = self.take_while(idx, |c| c != '\n' && c != '\r');
                     if self.comments {
                         lexemes.push(Lexeme {
                             slice,
@@ -129,6 +175,9 @@ impl<'a> Lexer<'a> {
 
                 // sections
                 '.' if self.peek_is(char::is_alphabetic) => {
+                    // a directive takes the place of an instruction, so any
+                    // identifiers after it are label references
+                    line_has_inst = true;
                     lexemes.push(Lexeme {
                         slice: self.take_while(idx, char::is_alphabetic),
                         line,
@@ -176,11 +225,22 @@ impl<'a> Lexer<'a> {
                     }
                 }
 
-                '-' if self.peek_is(char::is_numeric) => lexemes.push(Lexeme {
-                    slice: self.take_while(idx, char::is_numeric),
-                    line,
-                    kind: LexemeKind::Imm,
-                }),
+                '-' if self.peek_is(char::is_numeric) => {
+                    let slice = if self.text[idx + 1..].starts_with("0x") {
+                        // negative hexadecimal
+                        self.chars.next();
+                        self.chars.next();
+                        self.take_hex(idx)
+                    } else {
+                        self.take_decimal(idx)
+                    };
+
+                    lexemes.push(Lexeme {
+                        slice,
+                        line,
+                        kind: LexemeKind::Imm,
+                    })
+                }
 
                 // immediates
                 _ if c.is_numeric() => {
@@ -189,18 +249,32 @@ impl<'a> Lexer<'a> {
                         self.chars.next();
 
                         lexemes.push(Lexeme {
-                            slice: self.take_while(idx, |ref c| {
-                                c.is_numeric() || ('a'..='f').contains(c) || ('A'..='F').contains(c)
-                            }),
+                            slice: self.take_hex(idx),
                             line,
                             kind: LexemeKind::Imm,
                         })
                     } else {
-                        lexemes.push(Lexeme {
-                            slice: self.take_while(idx, char::is_numeric),
-                            line,
-                            kind: LexemeKind::Imm,
-                        });
+                        let mut slice = self.take_decimal(idx);
+                        let integer = !self.text[slice.clone()].contains('.');
+
+                        if integer && self.peek_is(|c| c == ':' || c == 'f' || c == 'b') {
+                            // a numeric local label, either a definition
+                            // (`1:`) or a reference (`1f`/`1b`)
+                            self.chars.next();
+                            slice.end = self.chars.offset();
+
+                            lexemes.push(Lexeme {
+                                slice,
+                                line,
+                                kind: LexemeKind::Label,
+                            });
+                        } else {
+                            lexemes.push(Lexeme {
+                                slice,
+                                line,
+                                kind: LexemeKind::Imm,
+                            });
+                        }
                     }
                 }
 
@@ -236,7 +310,8 @@ impl<'a> Lexer<'a> {
                     });
                 }
 
-                // whitespace
+                // whitespace, including the `\r` of CRLF line endings. only
+                // `\n` ends a line, matching how the editor lays out rows
                 _ if c.is_whitespace() => {
                     if self.whitespace {
                         self.append_or_add_lexeme(&mut lexemes, idx, line, LexemeKind::Whitespace);
@@ -248,6 +323,14 @@ impl<'a> Lexer<'a> {
                     }
                 }
 
+                // operators and grouping are never merged, so that the parser
+                // can consume them one at a time
+                _ if is_operator(c) => lexemes.push(Lexeme {
+                    slice: idx..self.chars.offset(),
+                    line,
+                    kind: LexemeKind::Punct,
+                }),
+
                 // catch all other characters into a Punct lexeme
                 _ => self.append_or_add_lexeme(&mut lexemes, idx, line, LexemeKind::Punct),
             }
@@ -283,3 +366,70 @@ impl<'a> Lexer<'a> {
             .collect::<_>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lex `text`, returning the kind and text of each lexeme.
+    fn lex(text: &str) -> Vec<(LexemeKind, &str)> {
+        Lexer::new(text)
+            .lex()
+            .into_iter()
+            .map(|l| (l.kind, &text[l.slice]))
+            .collect()
+    }
+
+    #[test]
+    fn label_then_instruction() {
+        use LexemeKind::*;
+
+        assert_eq!(
+            lex("loop: addi $t0,$t0,1"),
+            [
+                (Label, "loop:"),
+                (Inst, "addi"),
+                (Reg, "$t0"),
+                (Punct, ","),
+                (Reg, "$t0"),
+                (Punct, ","),
+                (Imm, "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn label_then_reference() {
+        use LexemeKind::*;
+
+        assert_eq!(
+            lex("loop: j loop"),
+            [(Label, "loop:"), (Inst, "j"), (Label, "loop")]
+        );
+    }
+
+    #[test]
+    fn two_labels_on_one_line() {
+        use LexemeKind::*;
+
+        assert_eq!(
+            lex("foo: bar:\nj foo"),
+            [
+                (Label, "foo:"),
+                (Label, "bar:"),
+                (Inst, "j"),
+                (Label, "foo")
+            ]
+        );
+    }
+
+    #[test]
+    fn directive_then_reference() {
+        use LexemeKind::*;
+
+        assert_eq!(
+            lex("ptr: .word loop"),
+            [(Label, "ptr:"), (Sect, ".word"), (Label, "loop")]
+        );
+    }
+}
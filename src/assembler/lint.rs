@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::simulator::{Registers, REG_FP, REG_RA, REG_S0, REG_S7, REG_SP, REG_V0};
+
+use super::{
+    inst::{InstArg, InstType, INST_ADDR_RELATIVE},
+    parser::{Node, NodeImm, NodeKind, Section},
+};
+
+/// Whether or not a function must preserve `reg` for its caller.
+fn is_callee_saved(reg: u8) -> bool {
+    matches!(reg, REG_S0..=REG_S7 | REG_SP | REG_FP | REG_RA)
+}
+
+/// The register a node writes, if any.
+fn written_reg(kind: &NodeKind) -> Option<u8> {
+    match kind {
+        NodeKind::InstR { inst, rt, rd, .. } => match inst.mnemonic {
+            "mfc0" => Some(*rt),
+            _ => matches!(inst.args[0], InstArg::Rd).then_some(*rd),
+        },
+        NodeKind::InstI { inst, rt, .. } => {
+            let store = inst.ty == InstType::Ils && inst.mnemonic.starts_with('s');
+            let branch = INST_ADDR_RELATIVE.contains(&inst.mnemonic);
+            (!store && !branch).then_some(*rt)
+        }
+        NodeKind::InstJ { inst, .. } => (inst.mnemonic == "jal").then_some(REG_RA),
+        NodeKind::InstPseudo { inst, rt, rd, .. } => match inst.args[0] {
+            InstArg::Rt => Some(*rt),
+            InstArg::Rd => Some(*rd),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A function being checked, from its label to the next function.
+struct Function<'a> {
+    name: &'a str,
+    line: u32,
+
+    /// The callee-saved registers the function writes, with the line of the
+    /// first write.
+    written: BTreeMap<u8, u32>,
+
+    /// The registers stored to the stack.
+    stored: HashSet<u8>,
+
+    /// The registers loaded from the stack.
+    loaded: HashSet<u8>,
+
+    /// The sum of the `$sp` adjustments, in bytes.
+    sp_offset: i32,
+
+    /// Whether or not `$sp` is written other than by adjusting it by a
+    /// constant, so its restoration can't be checked.
+    sp_unknown: bool,
+}
+
+impl<'a> Function<'a> {
+    fn new(name: &'a str, line: u32) -> Self {
+        Self {
+            name,
+            line,
+            written: BTreeMap::new(),
+            stored: HashSet::new(),
+            loaded: HashSet::new(),
+            sp_offset: 0,
+            sp_unknown: false,
+        }
+    }
+
+    fn visit(&mut self, node: &Node) {
+        let line = node.lexeme.line + 1;
+
+        if let NodeKind::InstI { inst, rs, rt, imm } = &node.kind {
+            match (inst.mnemonic, imm) {
+                ("sw", _) if *rs == REG_SP => {
+                    self.stored.insert(*rt);
+                    return;
+                }
+                ("lw", _) if *rs == REG_SP => {
+                    self.loaded.insert(*rt);
+                    return;
+                }
+                ("addi" | "addiu", NodeImm::Half(half)) if *rs == REG_SP && *rt == REG_SP => {
+                    self.sp_offset += *half as i16 as i32;
+                    return;
+                }
+                _ => (),
+            }
+        }
+
+        match written_reg(&node.kind) {
+            Some(REG_SP) => self.sp_unknown = true,
+            Some(reg) if is_callee_saved(reg) => {
+                self.written.entry(reg).or_insert(line);
+            }
+            _ => (),
+        }
+    }
+
+    fn finish(self, warnings: &mut Vec<String>) {
+        for (reg, line) in self.written {
+            if !self.stored.contains(&reg) || !self.loaded.contains(&reg) {
+                warnings.push(format!(
+                    "line {line}: {} modifies ${} without saving and restoring it",
+                    self.name,
+                    Registers::name(reg as usize),
+                ));
+            }
+        }
+
+        if !self.sp_unknown && self.sp_offset != 0 {
+            warnings.push(format!(
+                "line {}: {} does not restore $sp, which is off by {} bytes",
+                self.line, self.name, self.sp_offset,
+            ));
+        }
+    }
+}
+
+/// Warn about functions that modify a callee-saved register (`$s0`-`$s7`,
+/// `$sp`, `$fp` or `$ra`) without saving and restoring it.
+///
+/// This is a heuristic: functions are the labels targeted by `jal`, running
+/// until the next function or the end of the text section. A register counts
+/// as saved if the function both stores it to and loads it from the stack,
+/// and `$sp` as restored if its constant adjustments add up to zero.
+pub fn callee_saved(nodes: &[Node]) -> Vec<String> {
+    let entries = nodes
+        .iter()
+        .filter_map(|node| match &node.kind {
+            NodeKind::InstJ {
+                inst,
+                addr: NodeImm::Label(label),
+            } if inst.mnemonic == "jal" => Some(*label),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut warnings = vec![];
+    let mut function: Option<Function> = None;
+    let mut section = Section::Text;
+
+    for node in nodes {
+        match &node.kind {
+            NodeKind::Section(next) => {
+                section = *next;
+                if let Some(function) = function.take() {
+                    function.finish(&mut warnings);
+                }
+            }
+            NodeKind::Label(name) if section == Section::Text && entries.contains(name) => {
+                if let Some(function) = function.take() {
+                    function.finish(&mut warnings);
+                }
+                function = Some(Function::new(name, node.lexeme.line + 1));
+            }
+            _ => {
+                if let Some(function) = &mut function {
+                    function.visit(node);
+                }
+            }
+        }
+    }
+
+    if let Some(function) = function {
+        function.finish(&mut warnings);
+    }
+
+    warnings
+}
+
+/// The syscall services that exit the program.
+const EXIT_SERVICES: [u32; 2] = [10, 17];
+
+/// Warn about instructions that can never run because they follow an
+/// unconditional jump or an exit syscall with no label in between.
+///
+/// Exits are recognized when `$v0` was set to an exit service by an `li` or
+/// `addi`/`addiu`/`ori` from `$zero` since the last label.
+pub fn unreachable(nodes: &[Node]) -> Vec<String> {
+    let mut warnings = vec![];
+    let mut section = Section::Text;
+
+    // the instruction that ends the reachable code, and the line and count of
+    // the unreachable instructions following it
+    let mut end: Option<(&str, u32)> = None;
+    let mut dead: Option<(u32, usize)> = None;
+    let mut exit_v0 = false;
+
+    let mut finish = |end: &mut Option<(&str, u32)>, dead: &mut Option<(u32, usize)>| {
+        if let (Some((mnemonic, end_line)), Some((line, count))) = (end.take(), dead.take()) {
+            warnings.push(format!(
+                "line {line}: code after the {mnemonic} on line {end_line} is unreachable ({count} instruction{})",
+                if count == 1 { "" } else { "s" },
+            ));
+        }
+    };
+
+    for node in nodes {
+        let line = node.lexeme.line + 1;
+
+        let mnemonic = match &node.kind {
+            NodeKind::Section(next) => {
+                section = *next;
+                finish(&mut end, &mut dead);
+                exit_v0 = false;
+                continue;
+            }
+            NodeKind::Label(_) => {
+                finish(&mut end, &mut dead);
+                exit_v0 = false;
+                continue;
+            }
+            NodeKind::InstR { inst, .. }
+            | NodeKind::InstI { inst, .. }
+            | NodeKind::InstJ { inst, .. } => inst.mnemonic,
+            NodeKind::InstPseudo { inst, .. } => inst.mnemonic,
+            NodeKind::Directive(_) => continue,
+        };
+
+        if !matches!(section, Section::Text | Section::KText) {
+            continue;
+        }
+
+        if end.is_some() {
+            let (_, count) = dead.get_or_insert((line, 0));
+            *count += 1;
+            continue;
+        }
+
+        let v0_value = match &node.kind {
+            NodeKind::InstPseudo {
+                rt: REG_V0, addr, ..
+            } if mnemonic == "li" => Some(addr),
+            NodeKind::InstI {
+                rs: 0,
+                rt: REG_V0,
+                imm,
+                ..
+            } if matches!(mnemonic, "addi" | "addiu" | "ori") => Some(imm),
+            _ => None,
+        };
+        match v0_value {
+            Some(NodeImm::Half(value)) => exit_v0 = EXIT_SERVICES.contains(&(*value as u32)),
+            Some(NodeImm::Addr(value)) => exit_v0 = EXIT_SERVICES.contains(value),
+            _ if written_reg(&node.kind) == Some(REG_V0) => exit_v0 = false,
+            _ => (),
+        }
+
+        let terminates = match mnemonic {
+            "j" | "jr" | "b" | "eret" => true,
+            "syscall" => exit_v0,
+            _ => false,
+        };
+        if terminates {
+            end = Some((mnemonic, line));
+        }
+    }
+
+    finish(&mut end, &mut dead);
+    warnings
+}
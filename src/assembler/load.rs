@@ -0,0 +1,1179 @@
+#![allow(clippy::too_many_arguments)]
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+
+use crate::{
+    assembler::{
+        disasm,
+        expr::Expr,
+        inst::{
+            EncodeError, Inst, InstArg, InstCall, InstType, INST_ADDR_RELATIVE, INST_LARGE_IMM,
+            INST_MNEMONICS,
+        },
+        lint,
+        parser::{Directive, Node, NodeImm, NodeKind, Parser, Section},
+    },
+    simulator::{Memory, ADDR_KDATA, ADDR_KTEXT, ADDR_STATIC, ADDR_TEXT, REG_AT},
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AssembleError {
+    #[error("io error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("parse error: {0}")]
+    ParseError(String),
+    #[error("nothing to assemble")]
+    Empty,
+    #[error("line {0}: unknown label {1}")]
+    UnknownLabel(u32, String),
+    #[error("line {0}: duplicate label {1}, first defined on line {2}")]
+    DuplicateLabel(u32, String, u32),
+    #[error("line {0}: {1} must expand through $at, which is unavailable after .set noat")]
+    AtUnavailable(u32, &'static str),
+    #[error("line {0}: {1}")]
+    Encode(u32, EncodeError),
+}
+
+/// An assembled program, ready to be loaded into a processor.
+#[derive(Debug, Default, Clone)]
+pub struct AssembledProgram {
+    /// The encoded words of the text segment, starting at `ADDR_TEXT`.
+    pub text: Vec<u32>,
+
+    /// The bytes of the static data segment, starting at `ADDR_STATIC`.
+    pub data: Vec<u8>,
+
+    /// The address of the kernel text segment.
+    pub ktext_addr: usize,
+
+    /// The encoded words of the kernel text segment, starting at `ktext_addr`.
+    pub ktext: Vec<u32>,
+
+    /// The address of the kernel data segment.
+    pub kdata_addr: usize,
+
+    /// The bytes of the kernel data segment, starting at `kdata_addr`.
+    pub kdata: Vec<u8>,
+
+    /// A map of label to resolved address.
+    pub labels: HashMap<String, usize>,
+
+    /// A map of PC address to source line.
+    pub addr_lines: HashMap<usize, u32>,
+
+    /// A map of source line to the address of its first instruction, the
+    /// inverse of `addr_lines`.
+    pub line_addrs: HashMap<u32, usize>,
+
+    /// Warnings about the program, e.g. expanded instructions.
+    pub warnings: Vec<String>,
+
+    /// The pseudo instructions and oversized instructions, and what they
+    /// expanded into, in source order.
+    pub expansions: Vec<Expansion>,
+}
+
+impl AssembledProgram {
+    /// A one-line summary of where the program was laid out, e.g. for the
+    /// log after loading it.
+    pub fn summary(&self) -> String {
+        let code = |name: &str, start: usize, words: &[u32]| {
+            format!(
+                "{name} 0x{start:08x}-0x{:08x} ({} instruction{})",
+                start + words.len() * 4,
+                words.len(),
+                if words.len() == 1 { "" } else { "s" },
+            )
+        };
+
+        let mut summary = format!(
+            "entry 0x{ADDR_TEXT:08x}, {}, {} bytes of data",
+            code("text", ADDR_TEXT, &self.text),
+            self.data.len(),
+        );
+        if !self.ktext.is_empty() {
+            summary += &format!(", {}", code("kernel text", self.ktext_addr, &self.ktext));
+        }
+        if !self.kdata.is_empty() {
+            summary += &format!(", {} bytes of kernel data", self.kdata.len());
+        }
+
+        summary
+    }
+
+    /// The encoded words of the instructions on the source `lines`, in
+    /// address order, including everything pseudo instructions expand into.
+    pub fn line_words(&self, lines: RangeInclusive<u32>) -> Vec<u32> {
+        let mut addrs = self
+            .addr_lines
+            .iter()
+            .filter(|(_, line)| lines.contains(line))
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<_>>();
+        addrs.sort_unstable();
+
+        addrs
+            .into_iter()
+            .filter_map(|addr| {
+                let word = |start: usize, words: &[u32]| {
+                    addr.checked_sub(start)
+                        .and_then(|offset| words.get(offset / 4))
+                        .copied()
+                };
+                word(ADDR_TEXT, &self.text).or_else(|| word(self.ktext_addr, &self.ktext))
+            })
+            .collect()
+    }
+}
+
+/// An instruction that was expanded into other instructions.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    /// The source line of the instruction.
+    pub line: u32,
+
+    /// The instruction as written in the source.
+    pub source: String,
+
+    /// The disassembled instructions it expanded into.
+    pub insts: Vec<String>,
+}
+
+impl fmt::Display for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \u{2192} {}", self.source, self.insts.join("; "))
+    }
+}
+
+/// Options for assembling source code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleOptions {
+    /// Whether mnemonics and register names are matched regardless of case.
+    pub case_insensitive: bool,
+
+    /// The address `.ktext` sections are placed at.
+    pub ktext_addr: usize,
+
+    /// The address `.kdata` sections are placed at.
+    pub kdata_addr: usize,
+
+    /// Whether to warn about functions that modify callee-saved registers
+    /// without saving and restoring them.
+    pub lint_callee_saved: bool,
+
+    /// Whether to warn about code that follows an unconditional jump or an
+    /// exit with no label in between.
+    pub lint_unreachable: bool,
+}
+
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        Self {
+            case_insensitive: true,
+            ktext_addr: ADDR_KTEXT,
+            kdata_addr: ADDR_KDATA,
+            lint_callee_saved: false,
+            lint_unreachable: false,
+        }
+    }
+}
+
+/// Assemble some source code without loading it into a processor.
+pub fn assemble(
+    source: &str,
+    options: &AssembleOptions,
+) -> Result<AssembledProgram, AssembleError> {
+    let parser = Parser::new(source).with_case_insensitive(options.case_insensitive);
+    let parsed = parser
+        .parse()
+        .map_err(|e| AssembleError::ParseError(e.to_string()))?;
+
+    // blank or comment-only source
+    if parsed.is_empty() {
+        return Err(AssembleError::Empty);
+    }
+
+    let mut program = LoadContext::new(source, &parsed, options).load()?;
+    program.warnings.extend(parser.take_warnings());
+    if options.lint_callee_saved {
+        program.warnings.extend(lint::callee_saved(&parsed));
+    }
+    if options.lint_unreachable {
+        program.warnings.extend(lint::unreachable(&parsed));
+    }
+
+    Ok(program)
+}
+
+pub struct LoadContext<'a> {
+    /// The source code.
+    source: &'a str,
+
+    /// The options to assemble with.
+    options: &'a AssembleOptions,
+
+    /// The parsed nodes.
+    parsed: &'a [Node<'a>],
+
+    /// A map of label to address.
+    labels: HashMap<&'a str, usize>,
+
+    /// A map of label to the source line it is defined on.
+    label_lines: HashMap<&'a str, u32>,
+
+    /// A map of numeric local label (e.g. `1:`) to each of its definitions,
+    /// as pairs of source offset and address, in source order.
+    numeric_labels: HashMap<&'a str, Vec<(usize, usize)>>,
+
+    /// A vector of all nodes with labels.
+    nodes_with_labels: Vec<(usize, &'a Node<'a>)>,
+
+    /// A map of PC address to source line.
+    addr_lines: Vec<(usize, u32)>,
+
+    /// Warnings about the program, e.g. expanded instructions.
+    warnings: Vec<String>,
+
+    /// Whether expansions may use `$at`, toggled by `.set at` and `.set noat`.
+    at_allowed: bool,
+
+    /// The source lines of expansions that use `$at`.
+    at_expansions: Vec<u32>,
+
+    /// The source lines that use `$at` explicitly while expansions may use it.
+    at_uses: Vec<u32>,
+
+    /// Expanded instruction nodes, with the address range they expanded into.
+    expanded: Vec<(&'a Node<'a>, usize, usize)>,
+}
+
+impl<'a> LoadContext<'a> {
+    pub fn new(source: &'a str, parsed: &'a [Node<'a>], options: &'a AssembleOptions) -> Self {
+        Self {
+            source,
+            options,
+            parsed,
+            labels: HashMap::new(),
+            label_lines: HashMap::new(),
+            numeric_labels: HashMap::new(),
+            nodes_with_labels: Vec::new(),
+            addr_lines: Vec::new(),
+            warnings: Vec::new(),
+            at_allowed: true,
+            at_expansions: Vec::new(),
+            at_uses: Vec::new(),
+            expanded: Vec::new(),
+        }
+    }
+
+    pub fn load(mut self) -> Result<AssembledProgram, AssembleError> {
+        let mut mem = Memory::new();
+        mem.set_pos(ADDR_TEXT);
+
+        // the current section, and where each section continues from and ends
+        let mut section = Section::Text;
+        let mut cursors = HashMap::from(Section::ALL.map(|sec| (sec, self.section_addr(sec))));
+        let mut ends = cursors.clone();
+
+        for node in self.parsed.iter() {
+            let regs = match node.kind {
+                NodeKind::InstR { rs, rt, rd, .. } | NodeKind::InstPseudo { rs, rt, rd, .. } => {
+                    [rs, rt, rd]
+                }
+                NodeKind::InstI { rs, rt, .. } => [rs, rt, 0],
+                _ => [0; 3],
+            };
+            if self.at_allowed && regs.contains(&REG_AT) {
+                self.at_uses.push(node.lexeme.line);
+            }
+
+            // data in a code section or code in a data section is usually a
+            // missing section directive, but may be intentional
+            let code = matches!(section, Section::Text | Section::KText);
+            let misplaced = match &node.kind {
+                NodeKind::InstR { .. }
+                | NodeKind::InstI { .. }
+                | NodeKind::InstJ { .. }
+                | NodeKind::InstPseudo { .. } => (!code).then_some("instruction"),
+                NodeKind::Directive(Directive::Align(_) | Directive::SetAt(_)) => None,
+                NodeKind::Directive(_) => code.then_some("data directive"),
+                _ => None,
+            };
+            if let Some(what) = misplaced {
+                self.warnings.push(format!(
+                    "line {}: {what} in a {} section",
+                    node.lexeme.line + 1,
+                    if code { "code" } else { "data" },
+                ));
+            }
+
+            let start = mem.pos();
+
+            match &node.kind {
+                NodeKind::Section(sec) => {
+                    cursors.insert(section, mem.pos());
+                    section = *sec;
+                    mem.set_pos(cursors[sec]);
+                }
+
+                NodeKind::Label(label) if label.chars().all(|c| c.is_ascii_digit()) => {
+                    self.numeric_labels
+                        .entry(label)
+                        .or_default()
+                        .push((node.lexeme.slice.start, mem.pos()));
+                }
+
+                NodeKind::Label(label) => {
+                    if let Some(first) = self.label_lines.insert(label, node.lexeme.line) {
+                        return Err(AssembleError::DuplicateLabel(
+                            node.lexeme.line + 1,
+                            label.to_string(),
+                            first + 1,
+                        ));
+                    }
+                    self.labels.insert(label, mem.pos());
+                }
+
+                NodeKind::Directive(Directive::Byte(byte)) => mem.write_u8(*byte)?,
+                NodeKind::Directive(Directive::Half(half)) => mem.write_u16::<BE>(*half)?,
+                NodeKind::Directive(Directive::Word(word)) => mem.write_u32::<BE>(*word)?,
+                NodeKind::Directive(Directive::Float(float)) => mem.write_f32::<BE>(*float)?,
+                NodeKind::Directive(Directive::Double(double)) => mem.write_f64::<BE>(*double)?,
+                NodeKind::Directive(Directive::Asciiz(string)) => {
+                    mem.write_all(string.as_bytes())?;
+                    mem.write_u8(0)?;
+                }
+                NodeKind::Directive(Directive::Stringz(string)) => {
+                    mem.write_all(string.as_bytes())?;
+                    mem.write_u8(0)?;
+                    mem.align(4);
+                }
+                NodeKind::Directive(Directive::Align(pow)) => {
+                    mem.align(2usize.pow(*pow as u32));
+                }
+                NodeKind::Directive(Directive::SetAt(at)) => self.at_allowed = *at,
+
+                NodeKind::InstR {
+                    inst,
+                    rs,
+                    rt,
+                    rd,
+                    shamt,
+                } => {
+                    self.load_rtype(&mut mem, node, inst, *rs, *rt, *rd, *shamt)?;
+                }
+
+                NodeKind::InstI { inst, rs, rt, imm } => {
+                    let large_imm = INST_LARGE_IMM
+                        .iter()
+                        .find(|(mnemonic, _)| *mnemonic == inst.mnemonic);
+
+                    match (imm, large_imm) {
+                        (NodeImm::Addr(value), Some((_, reg_op))) => {
+                            self.load_large_imm(&mut mem, node, inst, reg_op, *rs, *rt, *value)?;
+                        }
+                        _ => self.load_itype(&mut mem, node, inst, *rs, *rt, imm)?,
+                    }
+                }
+
+                NodeKind::InstJ { inst, addr } => {
+                    self.load_jtype(&mut mem, node, inst, addr)?;
+                }
+
+                NodeKind::InstPseudo {
+                    inst,
+                    rs,
+                    rt,
+                    rd,
+                    addr,
+                } => match inst.mnemonic {
+                    "la" => {
+                        // push an addr ref at the first instruction
+                        self.nodes_with_labels.push((mem.pos(), node));
+                        self.load_itype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["lui"],
+                            0,
+                            *rt,
+                            &NodeImm::Half(0),
+                        )?;
+                        self.load_itype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["ori"],
+                            *rt,
+                            *rt,
+                            &NodeImm::Half(0),
+                        )?;
+                    }
+
+                    "nop" => {
+                        self.addr_lines.push((mem.pos(), node.lexeme.line));
+                        mem.write_u32::<BE>(0)?;
+                    }
+
+                    "li" => {
+                        let value = match addr {
+                            NodeImm::Addr(v) => *v,
+                            NodeImm::Half(v) => *v as u32,
+                            _ => unimplemented!(), // this shouldn't be reachable
+                        };
+
+                        if value > u16::MAX as u32 {
+                            self.load_itype(
+                                &mut mem,
+                                node,
+                                INST_MNEMONICS["lui"],
+                                0,
+                                *rt,
+                                &NodeImm::Half(((value & 0xffff0000) >> 16) as u16),
+                            )?;
+                            self.load_itype(
+                                &mut mem,
+                                node,
+                                INST_MNEMONICS["ori"],
+                                *rt,
+                                *rt,
+                                &NodeImm::Half((value & 0x0000ffff) as u16),
+                            )?;
+                        } else {
+                            // addi would sign-extend 0x8000-0xffff, so zero-extend them with ori
+                            let mnemonic = if value > i16::MAX as u32 {
+                                "ori"
+                            } else {
+                                "addi"
+                            };
+                            self.load_itype(
+                                &mut mem,
+                                node,
+                                INST_MNEMONICS[mnemonic],
+                                0,
+                                *rt,
+                                &NodeImm::Half((value & 0x0000ffff) as u16),
+                            )?;
+                        }
+                    }
+
+                    "move" => {
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["add"], *rs, 0, *rt, 0)?;
+                    }
+
+                    "b" => {
+                        self.load_itype(&mut mem, node, INST_MNEMONICS["beq"], 0, 0, addr)?;
+                    }
+
+                    "not" => {
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["nor"], *rs, 0, *rd, 0)?;
+                    }
+
+                    "neg" => {
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["sub"], 0, *rs, *rd, 0)?;
+                    }
+
+                    "abs" => {
+                        if !self.at_allowed {
+                            return Err(AssembleError::AtUnavailable(
+                                node.lexeme.line + 1,
+                                inst.mnemonic,
+                            ));
+                        }
+                        self.at_expansions.push(node.lexeme.line);
+
+                        // $at is all ones for negative values, so this flips
+                        // and increments them
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["sra"], 0, *rs, REG_AT, 31)?;
+                        self.load_rtype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["xor"],
+                            *rs,
+                            REG_AT,
+                            *rd,
+                            0,
+                        )?;
+                        self.load_rtype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["subu"],
+                            *rd,
+                            REG_AT,
+                            *rd,
+                            0,
+                        )?;
+                    }
+
+                    "mul" => {
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["mult"], *rs, *rt, 0, 0)?;
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["mflo"], 0, 0, *rd, 0)?;
+                    }
+
+                    "mulo" | "mulou" => {
+                        if !self.at_allowed {
+                            return Err(AssembleError::AtUnavailable(
+                                node.lexeme.line + 1,
+                                inst.mnemonic,
+                            ));
+                        }
+                        self.at_expansions.push(node.lexeme.line);
+
+                        // the product fits when the high word is the sign
+                        // extension of the low word, or zero when unsigned
+                        let signed = inst.mnemonic == "mulo";
+                        let mult = if signed { "mult" } else { "multu" };
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS[mult], *rs, *rt, 0, 0)?;
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["mfhi"], 0, 0, REG_AT, 0)?;
+                        let sign = if signed {
+                            self.load_rtype(&mut mem, node, INST_MNEMONICS["mflo"], 0, 0, *rd, 0)?;
+                            self.load_rtype(
+                                &mut mem,
+                                node,
+                                INST_MNEMONICS["sra"],
+                                0,
+                                *rd,
+                                *rd,
+                                31,
+                            )?;
+                            *rd
+                        } else {
+                            0
+                        };
+
+                        // skip over an add that always overflows
+                        self.load_itype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["beq"],
+                            sign,
+                            REG_AT,
+                            &NodeImm::Half(2),
+                        )?;
+                        self.load_itype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["lui"],
+                            0,
+                            REG_AT,
+                            &NodeImm::Half(0x8000),
+                        )?;
+                        self.load_rtype(
+                            &mut mem,
+                            node,
+                            INST_MNEMONICS["add"],
+                            REG_AT,
+                            REG_AT,
+                            REG_AT,
+                            0,
+                        )?;
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["mflo"], 0, 0, *rd, 0)?;
+                    }
+
+                    "rem" | "remu" => {
+                        let div = if inst.mnemonic == "rem" {
+                            "div"
+                        } else {
+                            "divu"
+                        };
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS[div], *rs, *rt, 0, 0)?;
+                        self.load_rtype(&mut mem, node, INST_MNEMONICS["mfhi"], 0, 0, *rd, 0)?;
+                    }
+
+                    _ => unimplemented!(),
+                },
+            }
+
+            // pseudo instructions, and instructions that didn't fit in one word
+            let expanded = match node.kind {
+                NodeKind::InstPseudo { .. } => true,
+                NodeKind::InstR { .. } | NodeKind::InstI { .. } | NodeKind::InstJ { .. } => {
+                    mem.pos() - start > 4
+                }
+                _ => false,
+            };
+            if expanded {
+                self.expanded.push((node, start, mem.pos()));
+            }
+
+            let end = ends.get_mut(&section).expect("section has no end");
+            *end = (*end).max(mem.pos());
+        }
+
+        for (addr, node) in std::mem::take(&mut self.nodes_with_labels) {
+            match &node.kind {
+                NodeKind::InstI { inst, imm, .. }
+                | NodeKind::InstJ {
+                    inst, addr: imm, ..
+                } => {
+                    mem.set_pos(addr);
+                    let mut encoded = mem.read_u32::<BE>()?;
+                    let label = match imm {
+                        NodeImm::Label(label) => self.resolve_label(node, label)?,
+                        NodeImm::Expr(expr) => self.eval_expr(node, expr)?,
+                        _ => unreachable!(),
+                    };
+
+                    // handle relative-addressed instructions
+                    if INST_ADDR_RELATIVE.contains(&inst.mnemonic) {
+                        let offset = (label as i64 - (addr as i64 + 4)) >> 2;
+                        encoded |= self.truncate_field(
+                            node,
+                            inst.mnemonic,
+                            "branch offset",
+                            offset,
+                            16,
+                            true,
+                        );
+                    } else if matches!(inst.ty, InstType::Ils) {
+                        // load-store offsets are byte offsets
+                        encoded |= self.truncate_field(
+                            node,
+                            inst.mnemonic,
+                            "offset",
+                            label as i64,
+                            16,
+                            true,
+                        );
+                    } else if matches!(inst.ty, InstType::J) {
+                        // jumps keep the upper 4 bits of the PC
+                        if (label ^ (addr + 4)) & 0xf0000000 != 0 {
+                            self.warnings.push(format!(
+                                "line {}: {} target 0x{label:08x} is outside the 256 MB region of the jump",
+                                node.lexeme.line + 1,
+                                inst.mnemonic,
+                            ));
+                        }
+                        encoded |= (label as u32 >> 2) & 0x3ffffff;
+                    } else {
+                        // other immediates are byte values, like literals
+                        encoded |= self.truncate_field(
+                            node,
+                            inst.mnemonic,
+                            "immediate",
+                            label as i64,
+                            16,
+                            inst.args.contains(&InstArg::SImm),
+                        );
+                    }
+
+                    mem.set_pos(addr);
+                    mem.write_u32::<BE>(encoded)?;
+                }
+
+                NodeKind::InstPseudo {
+                    inst,
+                    addr: inst_addr,
+                    rt: _rt,
+                    ..
+                } => {
+                    mem.set_pos(addr);
+
+                    match inst.mnemonic {
+                        "la" => {
+                            let mut lui = mem.read_u32::<BE>()?;
+                            let mut ori = mem.read_u32::<BE>()?;
+
+                            let target_addr = match inst_addr {
+                                NodeImm::Label(label) => self.resolve_label(node, label)?,
+                                NodeImm::Expr(expr) => self.eval_expr(node, expr)?,
+                                NodeImm::Half(half) => *half as usize,
+                                NodeImm::Addr(addr) => *addr as usize,
+                            };
+
+                            // store the upper 16 bits in lui and the lower 16 bits in ori
+                            lui = lui & 0xffff0000 | ((target_addr & 0xffff0000) >> 16) as u32;
+                            ori = ori & 0xffff0000 | (target_addr & 0x0000ffff) as u32;
+
+                            mem.set_pos(addr);
+                            mem.write_u32::<BE>(lui)?;
+                            mem.write_u32::<BE>(ori)?;
+                        }
+
+                        "b" => {
+                            let mut beq = mem.read_u32::<BE>()?;
+
+                            let target_addr = match inst_addr {
+                                NodeImm::Label(label) => self.resolve_label(node, label)?,
+                                NodeImm::Expr(expr) => self.eval_expr(node, expr)?,
+                                _ => unreachable!(),
+                            };
+
+                            // relative to the next instruction, like beq
+                            let offset = (target_addr as i64 - (addr as i64 + 4)) >> 2;
+                            beq |=
+                                self.truncate_field(node, "b", "branch offset", offset, 16, true);
+
+                            mem.set_pos(addr);
+                            mem.write_u32::<BE>(beq)?;
+                        }
+
+                        _ => unimplemented!(),
+                    }
+                }
+
+                _ => unreachable!(),
+            }
+        }
+
+        // expansions silently clobber any value kept in $at
+        if !self.at_expansions.is_empty() {
+            for line in self.at_uses.iter() {
+                self.warnings.push(format!(
+                    "line {}: $at is used explicitly, but is clobbered by expansions on line(s) {}; use .set noat to forbid them",
+                    line + 1,
+                    self.at_expansions
+                        .iter()
+                        .map(|line| (line + 1).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+            }
+        }
+
+        let mut expansions = vec![];
+        for (node, start, end) in std::mem::take(&mut self.expanded) {
+            let mut insts = vec![];
+            mem.set_pos(start);
+            while mem.pos() < end {
+                let word = mem.read_u32::<BE>()?;
+                insts.push(disasm::disassemble(word).unwrap_or_else(|| format!("0x{word:08x}")));
+            }
+
+            // the rest of the line from the mnemonic, without any comment
+            let line = self.source[node.lexeme.slice.start..]
+                .split(['\n', '#', ';'])
+                .next()
+                .unwrap_or_default();
+
+            expansions.push(Expansion {
+                line: node.lexeme.line,
+                source: line.trim().to_owned(),
+                insts,
+            });
+        }
+
+        // read back the contents of a section, as words for code
+        let read = |section: Section| -> io::Result<Vec<u8>> {
+            let start = self.section_addr(section);
+            let mut len = ends[&section] - start;
+            if matches!(section, Section::Text | Section::KText) {
+                len = len.div_ceil(4) * 4;
+            }
+
+            let mut bytes = vec![0u8; len];
+            mem.read_view(start, &mut bytes)?;
+            Ok(bytes)
+        };
+        let words = |bytes: Vec<u8>| -> Vec<u32> {
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+                .collect()
+        };
+
+        // lines that expand into several instructions map to the first
+        let mut line_addrs = HashMap::new();
+        for (addr, line) in self.addr_lines.iter() {
+            line_addrs.entry(*line).or_insert(*addr);
+        }
+
+        Ok(AssembledProgram {
+            text: words(read(Section::Text)?),
+            data: read(Section::Data)?,
+            ktext_addr: self.options.ktext_addr,
+            ktext: words(read(Section::KText)?),
+            kdata_addr: self.options.kdata_addr,
+            kdata: read(Section::KData)?,
+            labels: self
+                .labels
+                .into_iter()
+                .map(|(label, addr)| (label.to_owned(), addr))
+                .collect(),
+            addr_lines: self.addr_lines.into_iter().collect(),
+            line_addrs,
+            warnings: self.warnings,
+            expansions,
+        })
+    }
+
+    /// The address a section starts at.
+    fn section_addr(&self, section: Section) -> usize {
+        match section {
+            Section::Text => ADDR_TEXT,
+            Section::Data => ADDR_STATIC,
+            Section::KText => self.options.ktext_addr,
+            Section::KData => self.options.kdata_addr,
+        }
+    }
+
+    /// Resolve a label referenced by `node` to its address.
+    ///
+    /// Numeric local label references (`1f`/`1b`) resolve to the nearest
+    /// definition of that label after or before the reference in the source.
+    fn resolve_label(&self, node: &Node, label: &'a str) -> Result<usize, AssembleError> {
+        let numeric = label
+            .strip_suffix('f')
+            .map(|name| (name, true))
+            .or_else(|| label.strip_suffix('b').map(|name| (name, false)))
+            .filter(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()));
+
+        let addr = match numeric {
+            Some((name, forward)) => {
+                let defs = self.numeric_labels.get(name).map(Vec::as_slice);
+                let pos = node.lexeme.slice.start;
+
+                if forward {
+                    defs.and_then(|defs| defs.iter().find(|(offset, _)| *offset > pos))
+                } else {
+                    defs.and_then(|defs| defs.iter().rev().find(|(offset, _)| *offset < pos))
+                }
+                .map(|(_, addr)| *addr)
+            }
+            None => self.labels.get(label).copied(),
+        };
+
+        addr.ok_or_else(|| AssembleError::UnknownLabel(node.lexeme.line + 1, label.to_owned()))
+    }
+
+    /// Truncate `value` to a field of `bits` bits, warning if it does not fit.
+    /// Signed fields accept values down to the field's minimum.
+    fn truncate_field(
+        &mut self,
+        node: &Node,
+        mnemonic: &str,
+        field: &str,
+        value: i64,
+        bits: u32,
+        signed: bool,
+    ) -> u32 {
+        let truncated = value & ((1 << bits) - 1);
+        let (min, max) = if signed {
+            (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+        } else {
+            (0, (1 << bits) - 1)
+        };
+
+        if !(min..=max).contains(&value) {
+            // show what the field reads back as
+            let shown = if signed && truncated > max {
+                truncated - (1 << bits)
+            } else {
+                truncated
+            };
+            self.warnings.push(format!(
+                "line {}: {mnemonic} {field} {value} does not fit in {bits} bits, truncated to {shown}",
+                node.lexeme.line + 1,
+            ));
+        }
+
+        truncated as u32
+    }
+
+    /// Evaluate an immediate expression referenced by `node`.
+    fn eval_expr(&self, node: &Node, expr: &Expr<'a>) -> Result<usize, AssembleError> {
+        let value =
+            expr.eval(&mut |label| self.resolve_label(node, label).map(|addr| addr as i64))?;
+
+        Ok(value as usize)
+    }
+
+    pub fn load_rtype(
+        &mut self,
+        mem: &mut Memory,
+        node: &'a Node,
+        inst: &'static Inst,
+        rs: u8,
+        rt: u8,
+        rd: u8,
+        shamt: u8,
+    ) -> Result<(), AssembleError> {
+        self.addr_lines.push((mem.pos(), node.lexeme.line));
+
+        let call = InstCall {
+            inst,
+            rs,
+            rt,
+            rd,
+            shamt,
+            imm: 0,
+        };
+
+        self.write_call(mem, node, call)
+    }
+
+    pub fn load_itype(
+        &mut self,
+        mem: &mut Memory,
+        node: &'a Node,
+        inst: &'static Inst,
+        rs: u8,
+        rt: u8,
+        imm: &NodeImm,
+    ) -> Result<(), AssembleError> {
+        self.addr_lines.push((mem.pos(), node.lexeme.line));
+
+        let imm = match imm {
+            NodeImm::Half(half) => *half as u32,
+            NodeImm::Addr(addr) => *addr as u16 as u32 >> 2,
+            NodeImm::Label(_) | NodeImm::Expr(_) => {
+                self.nodes_with_labels.push((mem.pos(), node));
+                0
+            }
+        };
+
+        let call = InstCall {
+            inst,
+            rs,
+            rt,
+            rd: 0,
+            shamt: 0,
+            imm,
+        };
+
+        self.write_call(mem, node, call)
+    }
+
+    /// Load a logical immediate instruction, expanding it into a register-register
+    /// instruction through `$at` if the immediate does not fit in 16 bits.
+    pub fn load_large_imm(
+        &mut self,
+        mem: &mut Memory,
+        node: &'a Node,
+        inst: &'static Inst,
+        reg_op: &str,
+        rs: u8,
+        rt: u8,
+        value: u32,
+    ) -> Result<(), AssembleError> {
+        if value <= u16::MAX as u32 {
+            return self.load_itype(mem, node, inst, rs, rt, &NodeImm::Half(value as u16));
+        }
+
+        if !self.at_allowed {
+            return Err(AssembleError::AtUnavailable(
+                node.lexeme.line + 1,
+                inst.mnemonic,
+            ));
+        }
+
+        self.at_expansions.push(node.lexeme.line);
+        self.warnings.push(format!(
+            "line {}: {} immediate 0x{value:08x} does not fit in 16 bits, expanded through $at",
+            node.lexeme.line + 1,
+            inst.mnemonic,
+        ));
+
+        self.load_itype(
+            mem,
+            node,
+            INST_MNEMONICS["lui"],
+            0,
+            REG_AT,
+            &NodeImm::Half((value >> 16) as u16),
+        )?;
+        self.load_itype(
+            mem,
+            node,
+            INST_MNEMONICS["ori"],
+            REG_AT,
+            REG_AT,
+            &NodeImm::Half(value as u16),
+        )?;
+        self.load_rtype(mem, node, INST_MNEMONICS[reg_op], rs, REG_AT, rt, 0)
+    }
+
+    pub fn load_jtype(
+        &mut self,
+        mem: &mut Memory,
+        node: &'a Node,
+        inst: &'static Inst,
+        addr: &NodeImm,
+    ) -> Result<(), AssembleError> {
+        self.addr_lines.push((mem.pos(), node.lexeme.line));
+
+        let imm = match addr {
+            NodeImm::Half(half) => *half as u32 >> 2,
+            NodeImm::Addr(addr) => *addr >> 2,
+            NodeImm::Label(_) | NodeImm::Expr(_) => {
+                self.nodes_with_labels.push((mem.pos(), node));
+                0
+            }
+        };
+
+        let call = InstCall {
+            inst,
+            rs: 0,
+            rt: 0,
+            rd: 0,
+            shamt: 0,
+            imm,
+        };
+
+        self.write_call(mem, node, call)
+    }
+
+    /// Encode an instruction call of `node` and write it to memory.
+    fn write_call(
+        &mut self,
+        mem: &mut Memory,
+        node: &'a Node,
+        call: InstCall,
+    ) -> Result<(), AssembleError> {
+        let encoded = call
+            .encode()
+            .map_err(|e| AssembleError::Encode(node.lexeme.line + 1, e))?;
+        mem.write_u32::<BE>(encoded)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_ok(source: &str) -> AssembledProgram {
+        assemble(source, &AssembleOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn label_immediates_are_byte_values() {
+        let program = assemble_ok(
+            ".data\npad: .word 0\n.word 0\nval: .word 0\n.text\naddi $t0, $zero, val - pad\n",
+        );
+        assert_eq!(program.text[0] & 0xffff, 8);
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn negative_label_immediate_is_signed() {
+        let program = assemble_ok("start: nop\nend: addi $t0, $zero, start - end\n");
+        assert_eq!(program.text[1] & 0xffff, 0xfffc);
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn literal_sign_mismatch_warns() {
+        let program = assemble_ok("addi $t0, $t0, 0xFFFF\n");
+        assert_eq!(program.text[0] & 0xffff, 0xffff);
+        assert!(program.warnings[0].contains("reinterpreted as -1"));
+
+        let program = assemble_ok("lui $t0, -1\n");
+        assert!(program.warnings[0].contains("reinterpreted as 65535"));
+
+        let program = assemble_ok("addi $t0, $t0, -1\nori $t0, $t0, 0xFFFF\n");
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn logical_immediate_labels() {
+        let program = assemble_ok(
+            ".data\na: .word 0\nb: .word 0\n.text\nandi $t0, $t0, b - a\nori $t0, $zero, a\n",
+        );
+        assert_eq!(program.text.len(), 2);
+        assert_eq!(program.text[0] & 0xffff, 4);
+        assert!(program.warnings[0].contains("ori immediate"));
+    }
+
+    #[test]
+    fn constant_expression_immediate() {
+        let program = assemble_ok("addi $t0, $t0, 4*2\n");
+        assert_eq!(program.text[0], 0x21080008);
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn label_expression_immediate() {
+        let program = assemble_ok(".data\nARRAY: .word 0\n.text\naddi $t0, $t0, ARRAY+8\n");
+        // ARRAY+8 is 0x10010008, masked to its low 16 bits
+        assert_eq!(program.text[0], 0x21080008);
+        assert!(program.warnings[0].contains("truncated to 8"));
+    }
+
+    #[test]
+    fn label_expression_offset() {
+        let program = assemble_ok(".data\nARRAY: .word 0\n.text\nlw $t1, ARRAY+8($sp)\n");
+        assert_eq!(program.text[0], 0x8fa90008);
+        assert!(program.warnings[0].contains("truncated to 8"));
+
+        let program = assemble_ok("lw $t1, (4+4)*2($sp)\n");
+        assert_eq!(program.text[0], 0x8fa90010);
+    }
+
+    #[test]
+    fn expansion_source_drops_comments() {
+        let program = assemble_ok("li $t0, 0x12345 ; load\nli $t1, 0x12345 # load\n");
+        assert_eq!(program.expansions[0].source, "li $t0, 0x12345");
+        assert_eq!(program.expansions[1].source, "li $t1, 0x12345");
+        assert_eq!(program.expansions[0].insts.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_label() {
+        let error = assemble("foo: nop\nnop\nfoo: nop\n", &AssembleOptions::default()).unwrap_err();
+        assert!(matches!(
+            &error,
+            AssembleError::DuplicateLabel(3, label, 1) if label == "foo"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "line 3: duplicate label foo, first defined on line 1"
+        );
+    }
+
+    #[test]
+    fn assemble_headless() {
+        let program = assemble_ok(".data\nmsg: .byte 7\n.text\nmain: addi $t0, $zero, 1\nj main\n");
+        assert_eq!(program.text, [0x20080001, 0x08100000]);
+        assert_eq!(program.data[0], 7);
+        assert_eq!(program.labels["msg"], ADDR_STATIC);
+        assert_eq!(program.labels["main"], ADDR_TEXT);
+        assert_eq!(program.addr_lines[&ADDR_TEXT], 3);
+        assert_eq!(program.addr_lines[&(ADDR_TEXT + 4)], 4);
+        assert_eq!(program.line_addrs[&4], ADDR_TEXT + 4);
+    }
+
+    #[test]
+    fn unknown_label_line() {
+        let error = assemble("nop\nnop\nj missing\n", &AssembleOptions::default()).unwrap_err();
+        assert!(matches!(
+            &error,
+            AssembleError::UnknownLabel(3, label) if label == "missing"
+        ));
+        assert_eq!(error.to_string(), "line 3: unknown label missing");
+    }
+
+    #[test]
+    fn mixed_case() {
+        let lower = assemble_ok("add $t0, $t1, $t2\nlw $s0, 4($sp)\n");
+        let mixed = assemble_ok("ADD $T0, $t1, $T2\nLw $S0, 4($SP)\n");
+        assert_eq!(lower.text, mixed.text);
+
+        let options = AssembleOptions {
+            case_insensitive: false,
+            ..Default::default()
+        };
+        assert!(assemble("ADD $t0, $t1, $t2\n", &options).is_err());
+        assert!(assemble("add $T0, $t1, $t2\n", &options).is_err());
+    }
+
+    #[test]
+    fn mixed_case_keeps_labels_and_strings() {
+        let program = assemble_ok(".data\nMsg: .asciiz \"Hi\"\n.text\nLA $a0, Msg\n");
+        assert_eq!(program.labels["Msg"], ADDR_STATIC);
+        assert_eq!(&program.data[..3], b"Hi\0");
+
+        assert!(matches!(
+            assemble("Foo: j foo\n", &AssembleOptions::default()),
+            Err(AssembleError::UnknownLabel(1, _))
+        ));
+    }
+}
@@ -1,4 +1,11 @@
 pub mod directive;
+pub mod disasm;
+pub mod expr;
+pub mod format;
 pub mod inst;
 pub mod lexer;
+pub mod lint;
+pub mod load;
 pub mod parser;
+
+pub use load::{assemble, AssembleOptions, AssembledProgram};
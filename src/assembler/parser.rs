@@ -1,11 +1,19 @@
-use std::{cell::Cell, mem::transmute, num::ParseIntError};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    num::{ParseFloatError, ParseIntError},
+    str::FromStr,
+};
 
 use thiserror::Error;
 
 use crate::simulator::Registers;
 
 use super::{
-    inst::{Inst, InstArg, InstType, PseudoInst, INST_MNEMONICS, PSEUDO_INST_MNEMONICS},
+    expr::Expr,
+    inst::{
+        Inst, InstArg, InstType, PseudoInst, INST_LARGE_IMM, INST_MNEMONICS, PSEUDO_INST_MNEMONICS,
+    },
     lexer::{Lexeme, LexemeKind, Lexer},
 };
 
@@ -20,6 +28,8 @@ pub enum ParseError<'a> {
     UnexpectedLexeme(&'a Lexeme),
     #[error("integer parse error")]
     ParseIntError(#[from] ParseIntError),
+    #[error("float parse error")]
+    ParseFloatError(#[from] ParseFloatError),
     #[error("string parse error")]
     ParseStringError(&'a Lexeme),
     #[error("unterminated string at {0:?}")]
@@ -32,6 +42,10 @@ pub enum ParseError<'a> {
     ExpectedImm(Option<&'a Lexeme>),
     #[error("unknown register {0:?}")]
     UnknownRegister(&'a Lexeme),
+    #[error("immediate {0} out of range")]
+    ImmOutOfRange(i64),
+    #[error("unknown .set option \"{0}\"")]
+    UnknownSetOption(&'a str),
 }
 
 #[derive(Debug, Clone)]
@@ -97,13 +111,22 @@ pub enum NodeImm<'a> {
 
     /// A label reference. Dereferences to its address.
     Label(&'a str),
+
+    /// An expression referencing labels. Evaluated once labels are resolved.
+    Expr(Expr<'a>),
 }
 
 /// A section in the assembly, e.g. `.text` or `.data`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Section {
     Text,
     Data,
+    KText,
+    KData,
+}
+
+impl Section {
+    pub const ALL: [Self; 4] = [Self::Text, Self::Data, Self::KText, Self::KData];
 }
 
 #[derive(Debug, Clone)]
@@ -111,10 +134,14 @@ pub enum Directive {
     Byte(u8),
     Half(u16),
     Word(u32),
+    Float(f32),
+    Double(f64),
     Asciiz(String),
     /// Equivalent to `.asciiz "string" .align 2`.
     Stringz(String),
     Align(u8),
+    /// `.set at` or `.set noat`, whether expansions may use `$at`.
+    SetAt(bool),
 }
 
 #[derive(Debug, Default)]
@@ -122,8 +149,15 @@ pub struct Parser<'a> {
     source: &'a str,
     lexemes: Vec<Lexeme>,
 
+    /// Whether mnemonics and register names are matched regardless of case.
+    case_insensitive: bool,
+
     // TODO: does this need interior mutability?
     pos: Cell<usize>,
+
+    /// Warnings about the source, e.g. immediates reinterpreted to fit their
+    /// field.
+    warnings: RefCell<Vec<String>>,
 }
 
 impl<'a> Parser<'a> {
@@ -131,7 +165,30 @@ impl<'a> Parser<'a> {
         Self {
             source,
             lexemes: Lexer::new(source).lex(),
+            case_insensitive: true,
             pos: Cell::new(0),
+            warnings: RefCell::new(vec![]),
+        }
+    }
+
+    /// Take the warnings found while parsing.
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    /// Set whether mnemonics and register names are matched regardless of
+    /// case. Labels and strings are always case-sensitive.
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Normalize a mnemonic or register name for lookup.
+    fn normalize(&self, name: &'a str) -> Cow<'a, str> {
+        if self.case_insensitive {
+            Cow::Owned(name.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(name)
         }
     }
 
@@ -149,10 +206,6 @@ impl<'a> Parser<'a> {
             .map(|l| (l, &self.source[l.slice.clone()]))
     }
 
-    pub fn peek_kind(&'a self) -> Option<LexemeKind> {
-        self.peek().map(|l| l.0.kind)
-    }
-
     pub fn next(&'a self) -> Option<(&'a Lexeme, &'a str)> {
         match self.peek() {
             Some(l) => {
@@ -209,12 +262,6 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_i16(&'a self) -> Result<u16, ParseError<'a>> {
-        let (_, slice) = self.next_expect_kind(LexemeKind::Imm)?;
-
-        Ok(unsafe { transmute::<i16, u16>(str::parse::<i16>(slice)?) })
-    }
-
     pub fn parse_u32(&'a self) -> Result<u32, ParseError<'a>> {
         let (_, slice) = self.next_expect_kind(LexemeKind::Imm)?;
 
@@ -227,15 +274,138 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_i32(&'a self) -> Result<u32, ParseError<'a>> {
+    /// Parse a floating-point literal, e.g. `3.14`.
+    pub fn parse_float<F>(&'a self) -> Result<F, ParseError<'a>>
+    where
+        F: FromStr<Err = ParseFloatError>,
+    {
         let (_, slice) = self.next_expect_kind(LexemeKind::Imm)?;
 
-        if let Some(stripped) = slice.strip_prefix("0x") {
-            // hexadecimal
-            Ok(u32::from_str_radix(stripped, 16)?)
-        } else {
-            // try to parse normally
-            Ok(unsafe { transmute::<i32, u32>(str::parse(slice)?) })
+        Ok(str::parse(slice)?)
+    }
+
+    /// Parse an integer literal, e.g. `42`, `-1`, or `0xff`.
+    fn parse_int(slice: &str) -> Result<i64, ParseIntError> {
+        let (negative, slice) = match slice.strip_prefix('-') {
+            Some(stripped) => (true, stripped),
+            None => (false, slice),
+        };
+
+        let value = match slice.strip_prefix("0x") {
+            Some(stripped) => i64::from_str_radix(stripped, 16)?,
+            None => str::parse(slice)?,
+        };
+
+        Ok(if negative { -value } else { value })
+    }
+
+    /// Parse an immediate expression, e.g. `4*2` or `ARRAY+8`.
+    ///
+    /// The expression ends at the first lexeme that can't continue it, so the
+    /// base register parentheses of load-store operands are left alone.
+    pub fn parse_expr(&'a self) -> Result<Expr<'a>, ParseError<'a>> {
+        let line = self.peek().map(|(l, _)| l.line);
+        let mut expr = self.parse_expr_product()?;
+
+        loop {
+            match self.peek() {
+                Some((lexeme, slice)) if Some(lexeme.line) == line => match (lexeme.kind, slice) {
+                    (LexemeKind::Punct, "+") => {
+                        self.skip();
+                        expr = Expr::Add(Box::new(expr), Box::new(self.parse_expr_product()?));
+                    }
+                    (LexemeKind::Punct, "-") => {
+                        self.skip();
+                        expr = Expr::Sub(Box::new(expr), Box::new(self.parse_expr_product()?));
+                    }
+
+                    // `4-2` lexes as `4` followed by the immediate `-2`
+                    (LexemeKind::Imm, _) if slice.starts_with('-') => {
+                        expr = Expr::Add(Box::new(expr), Box::new(self.parse_expr_product()?));
+                    }
+
+                    _ => return Ok(expr),
+                },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_expr_product(&'a self) -> Result<Expr<'a>, ParseError<'a>> {
+        let mut expr = self.parse_expr_term()?;
+
+        while let Some((LexemeKind::Punct, "*")) = self.peek().map(|(l, s)| (l.kind, s)) {
+            self.skip();
+            expr = Expr::Mul(Box::new(expr), Box::new(self.parse_expr_term()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_expr_term(&'a self) -> Result<Expr<'a>, ParseError<'a>> {
+        let (lexeme, slice) = self.next().ok_or(ParseError::ExpectedImm(None))?;
+
+        match (lexeme.kind, slice) {
+            (LexemeKind::Imm, _) if !slice.starts_with('"') => {
+                Ok(Expr::Lit(Self::parse_int(slice)?))
+            }
+            (LexemeKind::Label, _) if !slice.ends_with(':') => Ok(Expr::Label(slice)),
+            (LexemeKind::Punct, "-") => Ok(Expr::Neg(Box::new(self.parse_expr_term()?))),
+            (LexemeKind::Punct, "(") => {
+                let expr = self.parse_expr()?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            }
+            _ => Err(ParseError::ExpectedImm(Some(lexeme))),
+        }
+    }
+
+    /// Parse an immediate instruction argument. Constant expressions are
+    /// folded and range-checked against the argument, while expressions
+    /// referencing labels are left for the assembler to resolve.
+    pub fn parse_imm(&'a self, arg: InstArg) -> Result<NodeImm<'a>, ParseError<'a>> {
+        let lexeme = self.peek().map(|l| l.0);
+        let expr = self.parse_expr()?;
+
+        match (expr.eval_const(), arg) {
+            (Some(value), InstArg::SImm | InstArg::UImm) => {
+                if !(i16::MIN as i64..=u16::MAX as i64).contains(&value) {
+                    return Err(ParseError::ImmOutOfRange(value));
+                }
+
+                // values of the other signedness fit in 16 bits, but read
+                // back differently
+                let signed = arg == InstArg::SImm;
+                let (reinterpreted, kind) = if signed {
+                    (value as u16 as i16 as i64, "signed")
+                } else {
+                    (value as u16 as i64, "unsigned")
+                };
+                if reinterpreted != value {
+                    self.warnings.borrow_mut().push(format!(
+                        "line {}: immediate {value} does not fit in a {kind} 16-bit field, reinterpreted as {reinterpreted}",
+                        lexeme.map(|l| l.line + 1).unwrap_or_default(),
+                    ));
+                }
+
+                Ok(NodeImm::Half(value as u16))
+            }
+            (Some(value), _) => {
+                if !(i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+                    return Err(ParseError::ImmOutOfRange(value));
+                }
+
+                Ok(NodeImm::Addr(value as u32))
+            }
+
+            // words are only used by pseudo instructions that need the value
+            // while assembling
+            (None, InstArg::Word) => Err(ParseError::ExpectedImm(lexeme)),
+
+            (None, _) => Ok(match expr {
+                Expr::Label(label) => NodeImm::Label(label),
+                expr => NodeImm::Expr(expr),
+            }),
         }
     }
 
@@ -277,7 +447,8 @@ impl<'a> Parser<'a> {
         let (lex, slice) = self.next_expect_kind(LexemeKind::Reg)?;
 
         if let Some(stripped) = slice.strip_prefix('$') {
-            Ok(Registers::index(stripped).ok_or(ParseError::UnknownRegister(lex))? as u8)
+            Ok(Registers::index(&self.normalize(stripped))
+                .ok_or(ParseError::UnknownRegister(lex))? as u8)
         } else {
             panic!("bad input to parser from lexer");
         }
@@ -301,6 +472,14 @@ impl<'a> Parser<'a> {
                             lexeme,
                             kind: NodeKind::Section(Section::Text),
                         }),
+                        "ktext" => nodes.push(Node {
+                            lexeme,
+                            kind: NodeKind::Section(Section::KText),
+                        }),
+                        "kdata" => nodes.push(Node {
+                            lexeme,
+                            kind: NodeKind::Section(Section::KData),
+                        }),
 
                         // TODO: it is assumed that each of these are unsigned
                         "byte" => nodes.push(Node {
@@ -316,6 +495,15 @@ impl<'a> Parser<'a> {
                             kind: NodeKind::Directive(Directive::Word(self.parse_u32()?)),
                         }),
 
+                        "float" => nodes.push(Node {
+                            lexeme,
+                            kind: NodeKind::Directive(Directive::Float(self.parse_float()?)),
+                        }),
+                        "double" => nodes.push(Node {
+                            lexeme,
+                            kind: NodeKind::Directive(Directive::Double(self.parse_float()?)),
+                        }),
+
                         "asciiz" => nodes.push(Node {
                             lexeme,
                             kind: NodeKind::Directive(Directive::Asciiz(self.parse_string()?)),
@@ -330,6 +518,20 @@ impl<'a> Parser<'a> {
                             kind: NodeKind::Directive(Directive::Align(self.parse_u8()?)),
                         }),
 
+                        "set" => {
+                            let (_, option) = self.next_expect_kind(LexemeKind::Label)?;
+                            let at = match option {
+                                "at" => true,
+                                "noat" => false,
+                                _ => return Err(ParseError::UnknownSetOption(option)),
+                            };
+
+                            nodes.push(Node {
+                                lexeme,
+                                kind: NodeKind::Directive(Directive::SetAt(at)),
+                            });
+                        }
+
                         _ => return Err(ParseError::UnknownSectDirective(name)),
                     };
                 }
@@ -348,8 +550,9 @@ impl<'a> Parser<'a> {
                 LexemeKind::Inst => {
                     // TODO: this code is awful, how can we do this better
 
-                    let inst = INST_MNEMONICS.get(slice);
-                    let pseudo_inst = PSEUDO_INST_MNEMONICS.get(slice);
+                    let mnemonic = self.normalize(slice);
+                    let inst = INST_MNEMONICS.get(&*mnemonic);
+                    let pseudo_inst = PSEUDO_INST_MNEMONICS.get(&*mnemonic);
 
                     if inst.is_none() && pseudo_inst.is_none() {
                         return Err(ParseError::UnknownInstruction(slice));
@@ -361,6 +564,16 @@ impl<'a> Parser<'a> {
                         false
                     };
 
+                    // logical immediates accept 32-bit values, which the
+                    // assembler expands through `$at`
+                    let large_imm = if let Some(inst) = inst {
+                        INST_LARGE_IMM
+                            .iter()
+                            .any(|(mnemonic, _)| *mnemonic == inst.mnemonic)
+                    } else {
+                        false
+                    };
+
                     let mut rs = 0;
                     let mut rt = 0;
                     let mut rd = 0;
@@ -403,39 +616,12 @@ impl<'a> Parser<'a> {
                             InstArg::Shamt => {
                                 shamt = self.parse_u8()?;
                             }
-                            InstArg::SImm => match self.peek_kind() {
-                                Some(LexemeKind::Imm) => {
-                                    imm = NodeImm::Half(self.parse_i16()?);
-                                }
-                                Some(LexemeKind::Label) => {
-                                    imm = NodeImm::Label(self.next().unwrap().1);
-                                }
-                                _ => return Err(ParseError::ExpectedImm(self.next().map(|l| l.0))),
-                            },
-                            InstArg::UImm => match self.peek_kind() {
-                                Some(LexemeKind::Imm) => {
-                                    imm = NodeImm::Half(self.parse_u16()?);
-                                }
-                                Some(LexemeKind::Label) => {
-                                    imm = NodeImm::Label(self.next().unwrap().1);
-                                }
-                                _ => return Err(ParseError::ExpectedImm(self.next().map(|l| l.0))),
-                            },
-                            InstArg::Addr => match self.peek_kind() {
-                                Some(LexemeKind::Imm) => {
-                                    imm = NodeImm::Addr(self.parse_u32()?);
-                                }
-                                Some(LexemeKind::Label) => {
-                                    imm = NodeImm::Label(self.next().unwrap().1);
-                                }
-                                _ => return Err(ParseError::ExpectedImm(self.next().map(|l| l.0))),
-                            },
-                            InstArg::Word => match self.peek_kind() {
-                                Some(LexemeKind::Imm) => {
-                                    imm = NodeImm::Addr(self.parse_i32()?);
-                                }
-                                _ => return Err(ParseError::ExpectedImm(self.next().map(|l| l.0))),
-                            },
+                            InstArg::SImm | InstArg::UImm if large_imm => {
+                                imm = self.parse_imm(InstArg::Addr)?;
+                            }
+                            InstArg::SImm | InstArg::UImm | InstArg::Addr | InstArg::Word => {
+                                imm = self.parse_imm(*arg)?;
+                            }
                         }
 
                         if ty_ils && i == 2 {
@@ -478,3 +664,37 @@ impl<'a> Parser<'a> {
         Ok(nodes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_int_literals() {
+        assert_eq!(Parser::parse_int("-4"), Ok(-4));
+        assert_eq!(Parser::parse_int("-0x10"), Ok(-16));
+        assert_eq!(Parser::parse_int("0xFFFC"), Ok(0xfffc));
+    }
+
+    #[test]
+    fn load_store_offsets() {
+        for (source, expected, warns) in [
+            ("lw $t0, -4($sp)", 0xfffc, false),
+            ("lw $t0, -0x10($sp)", 0xfff0, false),
+            ("lw $t0, 0xFFFC($sp)", 0xfffc, true),
+        ] {
+            let parser = Parser::new(source);
+            let nodes = parser.parse().unwrap();
+            assert!(matches!(
+                nodes[0].kind,
+                NodeKind::InstI {
+                    rs: 29,
+                    rt: 8,
+                    imm: NodeImm::Half(imm),
+                    ..
+                } if imm == expected
+            ));
+            assert_eq!(!parser.take_warnings().is_empty(), warns);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    assembler::assemble,
+    simulator::{AppMessage, ProcMessage, Processor, DEFAULT_MAX_STEPS},
+};
+
+const USAGE: &str = "usage: mipsim --run <program.s> [--input <input.txt>] [--max-steps <n>]";
+
+/// The exit code when the simulator itself fails, e.g. on bad arguments or
+/// a program that does not assemble.
+const EXIT_ERROR: i32 = 125;
+
+/// The exit code when the program does not exit cleanly, e.g. on a step
+/// error or when it runs out of steps.
+const EXIT_HALTED: i32 = 124;
+
+/// Options of a headless run.
+struct CliOptions {
+    program: PathBuf,
+    input: Option<PathBuf>,
+    max_steps: u64,
+}
+
+impl CliOptions {
+    /// Parse the command line arguments, excluding the binary name.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut program = None;
+        let mut input = None;
+        let mut max_steps = DEFAULT_MAX_STEPS;
+
+        while let Some(arg) = args.next() {
+            if !matches!(arg.as_str(), "--run" | "--input" | "--max-steps") {
+                return Err(format!("unknown argument {arg}"));
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {arg}"))?;
+
+            match arg.as_str() {
+                "--run" => program = Some(value.into()),
+                "--input" => input = Some(value.into()),
+                _ => {
+                    max_steps = value
+                        .parse()
+                        .map_err(|_| format!("invalid step count {value}"))?
+                }
+            }
+        }
+
+        Ok(Self {
+            program: program.ok_or("missing --run")?,
+            input,
+            max_steps,
+        })
+    }
+}
+
+/// Run a program without the GUI if `--run` was given, returning the exit
+/// code. Program output is printed to stdout, and the lines of the input file
+/// are fed to the read syscalls.
+pub fn run() -> Option<i32> {
+    if !std::env::args().any(|arg| arg == "--run") {
+        return None;
+    }
+
+    let options = match CliOptions::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!(
+                "{e}\n{USAGE}\n\n\
+                 Exits with the program's exit code, or 0 if it exits without one. If the\n\
+                 program runs into an error or more than --max-steps instructions\n\
+                 ({DEFAULT_MAX_STEPS} by default), exits with {EXIT_HALTED}; if the simulator fails,\n\
+                 exits with {EXIT_ERROR}. A program exiting with either code itself can't be\n\
+                 told apart from these."
+            );
+            return Some(EXIT_ERROR);
+        }
+    };
+
+    Some(match run_headless(&options) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            EXIT_ERROR
+        }
+    })
+}
+
+fn run_headless(options: &CliOptions) -> Result<i32, String> {
+    let source = fs::read_to_string(&options.program)
+        .map_err(|e| format!("failed to read {}: {e}", options.program.display()))?;
+
+    let program =
+        assemble(&source, &Default::default()).map_err(|e| format!("Assemble error: {e}"))?;
+    for warning in program.warnings.iter() {
+        eprintln!("Warning: {warning}");
+    }
+
+    let (proc_tx, proc_rx) = crossbeam::channel::unbounded();
+    let (app_tx, app_rx) = crossbeam::channel::unbounded();
+
+    // queue every line of input up front; once they run out, the read
+    // syscalls fail instead of waiting
+    if let Some(path) = &options.input {
+        let input = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        for line in input.lines() {
+            proc_tx.send(ProcMessage::Io(line.to_owned())).unwrap();
+        }
+    }
+    drop(proc_tx);
+
+    let mut proc = Processor::new(app_tx, proc_rx);
+    proc.load(&program)
+        .map_err(|e| format!("Load error: {e}"))?;
+
+    let mut stdout = io::stdout().lock();
+    let mut steps = 0;
+    let mut code = loop {
+        if !proc.active {
+            break proc.exit_code.unwrap_or(0);
+        }
+
+        if steps >= options.max_steps {
+            eprintln!("Stopped after {steps} steps");
+            break EXIT_HALTED;
+        }
+
+        let result = proc.step();
+        steps += 1;
+
+        for message in app_rx.try_iter() {
+            if let AppMessage::Io(string) = message {
+                let _ = stdout.write_all(string.as_bytes());
+            }
+        }
+
+        if let Err(e) = result {
+            eprintln!("Step error: {e}");
+            break EXIT_HALTED;
+        }
+    };
+
+    if stdout.flush().is_err() {
+        code = EXIT_ERROR;
+    }
+
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_steps_defaults_to_run_limit() {
+        let args = ["--run", "a.s"].map(String::from);
+        let options = CliOptions::parse(args.into_iter()).unwrap();
+        assert_eq!(options.max_steps, DEFAULT_MAX_STEPS);
+
+        let args = ["--run", "a.s", "--max-steps", "5"].map(String::from);
+        let options = CliOptions::parse(args.into_iter()).unwrap();
+        assert_eq!(options.max_steps, 5);
+    }
+}
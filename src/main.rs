@@ -4,55 +4,117 @@ use simulator::{ProcSpawn, Processor};
 
 mod app;
 mod assembler;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
 mod simulator;
 mod util;
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let ProcSpawn {
-        proc_tx,
-        app_rx,
-        mem,
-    } = Processor::spawn();
+    // run headless if given a program to run
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
+
+    let spawn = Processor::spawn();
 
     eframe::run_native(
         "mipsim",
         eframe::NativeOptions::default(),
-        Box::new(|_| {
-            let mut tree = egui_dock::Tree::new(vec![AppTab::Editor, AppTab::Memory]);
-
-            let [node_editor, _] =
-                tree.split_right(NodeIndex::root(), 0.8, vec![AppTab::Registers]);
-
-            let [_, _] = tree.split_below(node_editor, 0.8, vec![AppTab::Log, AppTab::Io]);
-
-            let container = Box::new(AppContainer {
-                app: App::new(proc_tx, app_rx, mem),
-                tree,
-            });
-
-            container
-                .app
-                .output
-                .log
-                .tx
-                .send("Welcome to mipsim!".into())
-                .unwrap();
-            container
-        }),
+        Box::new(|cc| Box::new(AppContainer::new(cc, spawn))),
     )
     .unwrap();
 }
 
+/// Start the app in the canvas of `index.html`. Without threads, the
+/// processor runs between frames instead.
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    let (proc, spawn) = Processor::local();
+
+    wasm_bindgen_futures::spawn_local(async move {
+        eframe::start_web(
+            "mipsim_canvas",
+            eframe::WebOptions::default(),
+            Box::new(|cc| Box::new(AppContainer::new(cc, spawn, proc))),
+        )
+        .await
+        .expect("failed to start mipsim");
+    });
+}
+
 pub struct AppContainer {
     pub app: App,
     pub tree: egui_dock::Tree<AppTab>,
+
+    /// The processor, driven from the frame loop.
+    #[cfg(target_arch = "wasm32")]
+    pub proc: Processor,
+}
+
+impl AppContainer {
+    fn new(
+        cc: &eframe::CreationContext<'_>,
+        spawn: ProcSpawn,
+        #[cfg(target_arch = "wasm32")] proc: Processor,
+    ) -> Self {
+        let ProcSpawn {
+            proc_tx,
+            app_rx,
+            mem,
+        } = spawn;
+
+        let mut tree = egui_dock::Tree::new(vec![AppTab::Editor(0), AppTab::Memory]);
+
+        let [node_editor, _] = tree.split_right(NodeIndex::root(), 0.8, vec![AppTab::Registers]);
+
+        let [_, _] = tree.split_below(
+            node_editor,
+            0.8,
+            vec![AppTab::Log, AppTab::Io, AppTab::Encoding, AppTab::Console],
+        );
+
+        let container = AppContainer {
+            app: App::new(proc_tx, app_rx, mem),
+            tree,
+            #[cfg(target_arch = "wasm32")]
+            proc,
+        };
+
+        cc.egui_ctx
+            .set_visuals(container.app.settings.theme.visuals());
+
+        container
+            .app
+            .output
+            .log
+            .tx
+            .send("Welcome to mipsim!".into())
+            .unwrap();
+        container
+    }
 }
 
 impl eframe::App for AppContainer {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        #[cfg(target_arch = "wasm32")]
+        self.proc.pump();
+
         self.app.update(ctx, frame);
 
         app::menu::show_menu_bar(self, ctx, frame);
+        app::menu::palette::show_palette(&mut self.app, ctx, frame);
+        app::menu::goto_line::show_goto_line(&mut self.app, ctx);
+        app::settings::Settings::show(&mut self.app, ctx);
+        app::status::show_status_bar(&mut self.app, ctx);
+
+        // open new editors next to the first one
+        for tab in self.app.new_tabs.drain(..) {
+            if let Some((node, _)) = self.tree.find_tab(&AppTab::Editor(0)) {
+                self.tree.set_focused_node(node);
+            }
+            self.tree.push_to_focused_leaf(tab);
+        }
 
         egui::CentralPanel::default()
             .frame(egui::Frame::central_panel(&ctx.style()).inner_margin(0.0))
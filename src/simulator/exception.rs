@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// An exception raised while executing an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// A load from a misaligned or out of range address.
+    AddressLoad(u32),
+
+    /// A store to a misaligned or out of range address.
+    AddressStore(u32),
+
+    /// An instruction word that does not decode to a known instruction.
+    ReservedInstruction(u32),
+
+    /// A `syscall` with a service number the processor does not implement.
+    Syscall(u32),
+
+    /// A signed arithmetic overflow in `add`, `addi` or `sub`.
+    Overflow,
+}
+
+impl Exception {
+    /// The exception code stored in the `ExcCode` field of the Cause register.
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::AddressLoad(_) => 4,
+            Self::AddressStore(_) => 5,
+            Self::Syscall(_) => 8,
+            Self::ReservedInstruction(_) => 10,
+            Self::Overflow => 12,
+        }
+    }
+
+    /// The faulting address, for address errors.
+    pub fn bad_addr(&self) -> Option<u32> {
+        match self {
+            Self::AddressLoad(addr) | Self::AddressStore(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressLoad(addr) => write!(f, "address error on load from 0x{addr:08x}"),
+            Self::AddressStore(addr) => write!(f, "address error on store to 0x{addr:08x}"),
+            Self::Syscall(code) => write!(f, "unimplemented syscall {code}"),
+            Self::ReservedInstruction(word) => write!(f, "unknown instruction 0x{word:08x}"),
+            Self::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+/// The coprocessor 0 registers, read and written with `mfc0` and `mtc0`.
+/// An exception handler reads them to find out why and where the program
+/// trapped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cp0 {
+    /// The faulting address of the last address error.
+    pub bad_vaddr: u32,
+
+    /// The processor status.
+    pub status: u32,
+
+    /// The cause of the last exception, with the exception code in bits 2-6.
+    pub cause: u32,
+
+    /// The address of the instruction that raised the last exception. `eret`
+    /// returns to it, so a handler adds 4 to skip the faulting instruction.
+    pub epc: u32,
+}
+
+impl Cp0 {
+    /// The implemented registers, as register number and name.
+    pub const REGISTERS: [(u8, &'static str); 4] =
+        [(8, "BadVAddr"), (12, "Status"), (13, "Cause"), (14, "EPC")];
+
+    /// Read a register by number. Unimplemented registers read as zero.
+    pub fn get(&self, index: u8) -> u32 {
+        match index {
+            8 => self.bad_vaddr,
+            12 => self.status,
+            13 => self.cause,
+            14 => self.epc,
+            _ => 0,
+        }
+    }
+
+    /// Write a register by number. Writes to BadVAddr and unimplemented
+    /// registers are ignored.
+    pub fn set(&mut self, index: u8, value: u32) {
+        match index {
+            12 => self.status = value,
+            13 => self.cause = value,
+            14 => self.epc = value,
+            _ => (),
+        }
+    }
+}
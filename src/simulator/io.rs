@@ -1,8 +1,15 @@
+/// The line marking where a new run begins when output is kept.
+const RUN_SEPARATOR: &str = "---------- new run ----------";
+
 #[derive(Debug, Default)]
 pub struct Io {
     pub lines: Vec<String>,
     pub buf: String,
     pub in_buf: String,
+
+    /// Whether the processor is waiting on a character read, so keystrokes
+    /// are sent as they are typed instead of a line at a time.
+    pub awaiting_char: bool,
 }
 
 impl Io {
@@ -15,6 +22,18 @@ impl Io {
         std::mem::take(&mut self.buf);
     }
 
+    /// Mark where a new run begins, keeping the output of the previous one.
+    pub fn separate(&mut self) {
+        if self.lines.is_empty() && self.buf.is_empty() {
+            return;
+        }
+
+        if !self.buf.is_empty() {
+            self.lines.push(std::mem::take(&mut self.buf));
+        }
+        self.lines.push(RUN_SEPARATOR.to_owned());
+    }
+
     pub fn add(&mut self, string: String) {
         for c in string.chars() {
             match c {
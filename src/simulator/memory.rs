@@ -1,22 +1,41 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
 };
 
 /// The length of a single block.
 pub const BLOCK_SIZE: usize = 256;
+/// One past the highest address. It doesn't fit in a 32-bit `usize`, so
+/// there the last word is left out.
+#[cfg(target_pointer_width = "64")]
 pub const ADDR_MEM_MAX: usize = 0x100000000;
+#[cfg(not(target_pointer_width = "64"))]
+pub const ADDR_MEM_MAX: usize = 0xfffffffc;
 pub const ADDR_STACK_TOP: usize = 0x80000000;
 pub const ADDR_HEAP: usize = 0x10008000;
 pub const ADDR_STATIC: usize = 0x10000000;
 pub const ADDR_TEXT: usize = 0x00400000;
+pub const ADDR_KTEXT: usize = 0x80000000;
+pub const ADDR_KDATA: usize = 0x90000000;
 
-type Block = [u8; BLOCK_SIZE];
+/// The magic bytes at the start of a memory snapshot file.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"MIPSMEM\0";
+
+pub type Block = [u8; BLOCK_SIZE];
 
 #[derive(Debug, Default)]
 pub struct Memory {
     tree: BTreeMap<usize, Block>,
     pos: usize,
+
+    /// The PC of the instruction currently executing, recorded as the writer
+    /// of each word written. `None` for writes not made by the program.
+    pub writer: Option<usize>,
+
+    /// The PC of the instruction that last wrote each word, by word address.
+    /// `None` if writer tracking is disabled.
+    writers: Option<BTreeMap<usize, usize>>,
 }
 
 impl Memory {
@@ -27,6 +46,27 @@ impl Memory {
     pub fn reset(&mut self) {
         self.tree.clear();
         self.pos = 0;
+        self.writer = None;
+        if let Some(writers) = &mut self.writers {
+            writers.clear();
+        }
+    }
+
+    /// Enable or disable tracking the writer of each word. Disabling
+    /// forgets every writer recorded so far.
+    pub fn set_track_writers(&mut self, track: bool) {
+        if track != self.writers.is_some() {
+            self.writers = track.then(BTreeMap::new);
+        }
+    }
+
+    /// The recorded writers of the words in `range`, as pairs of word address
+    /// and writer PC.
+    pub fn writers(&self, range: Range<usize>) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.writers
+            .iter()
+            .flat_map(move |writers| writers.range(range.clone()))
+            .map(|(addr, pc)| (*addr, *pc))
     }
 
     pub fn pos(&self) -> usize {
@@ -44,39 +84,207 @@ impl Memory {
         }
     }
 
-    /// Get all of the block addresses that contain the start address and the size.
-    fn block_addrs(&self, start_addr: usize, size: usize) -> Vec<usize> {
-        let mut addrs = vec![start_addr / BLOCK_SIZE * BLOCK_SIZE];
+    /// Take a snapshot of every allocated block, as pairs of block address
+    /// and block contents.
+    pub fn snapshot(&self) -> Vec<(usize, Block)> {
+        self.tree
+            .iter()
+            .map(|(addr, block)| (*addr, *block))
+            .collect()
+    }
+
+    /// Restore the blocks of a snapshot, overwriting any blocks already
+    /// allocated at the same addresses. The writers recorded in those blocks
+    /// are forgotten.
+    pub fn restore(&mut self, blocks: Vec<(usize, Block)>) {
+        if let Some(writers) = &mut self.writers {
+            let restored = blocks
+                .iter()
+                .map(|(addr, _)| *addr)
+                .collect::<BTreeSet<_>>();
+            writers.retain(|addr, _| !restored.contains(&(addr / BLOCK_SIZE * BLOCK_SIZE)));
+        }
+
+        self.tree.extend(blocks);
+    }
+
+    /// Zero the bytes in `range`, freeing any blocks entirely inside it.
+    pub fn clear(&mut self, range: Range<usize>) {
+        if let Some(writers) = &mut self.writers {
+            writers.retain(|addr, _| !range.contains(addr));
+        }
+
+        let first = range.start / BLOCK_SIZE * BLOCK_SIZE;
+        let addrs = self
+            .tree
+            .range(first..range.end)
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<_>>();
+
+        for addr in addrs {
+            if range.start <= addr && addr + BLOCK_SIZE <= range.end {
+                self.tree.remove(&addr);
+            } else if let Some(block) = self.tree.get_mut(&addr) {
+                let start = range.start.max(addr) - addr;
+                let end = range.end.min(addr + BLOCK_SIZE) - addr;
+                block[start..end].fill(0);
+            }
+        }
+    }
+
+    /// Fill `len` bytes from `start` with `pattern` repeated. The bytes go
+    /// through `write`, so blocks are allocated as needed.
+    pub fn fill(&mut self, start: usize, len: usize, pattern: &[u8]) -> io::Result<()> {
+        if pattern.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "fill pattern is empty",
+            ));
+        }
+        Self::access_end(start, len)?;
 
-        while addrs[addrs.len() - 1] + BLOCK_SIZE < start_addr + size {
-            addrs.push(addrs[addrs.len() - 1] + BLOCK_SIZE);
+        self.pos = start;
+
+        // write a block at a time, continuing the pattern across blocks
+        let mut written = 0;
+        while written < len {
+            let chunk = pattern
+                .iter()
+                .copied()
+                .cycle()
+                .skip(written % pattern.len())
+                .take(BLOCK_SIZE.min(len - written))
+                .collect::<Vec<_>>();
+            self.write_all(&chunk)?;
+            written += chunk.len();
         }
 
+        Ok(())
+    }
+
+    /// The addresses of every byte that differs between this memory and
+    /// `other`, in order. Unallocated blocks compare as zeroes.
+    pub fn diff(&self, other: &Memory) -> Vec<usize> {
+        const ZERO: Block = [0; BLOCK_SIZE];
+
+        let addrs = self
+            .tree
+            .keys()
+            .chain(other.tree.keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+
         addrs
+            .into_iter()
+            .flat_map(|addr| {
+                let a = self.tree.get(&addr).unwrap_or(&ZERO);
+                let b = other.tree.get(&addr).unwrap_or(&ZERO);
+                (0..BLOCK_SIZE)
+                    .filter(move |i| a[*i] != b[*i])
+                    .map(move |i| addr + i)
+            })
+            .collect()
+    }
+
+    /// Write a snapshot of the memory in a simple binary format: a magic
+    /// header followed by each block's address (as a BE `u32`) and contents.
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+
+        for (addr, block) in self.tree.iter() {
+            writer.write_all(&(*addr as u32).to_be_bytes())?;
+            writer.write_all(block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a snapshot written by [`Memory::write_snapshot`].
+    pub fn read_snapshot<R: Read>(mut reader: R) -> io::Result<Vec<(usize, Block)>> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a memory snapshot",
+            ));
+        }
+
+        let mut blocks = vec![];
+        let mut addr = [0u8; 4];
+        loop {
+            match reader.read_exact(&mut addr) {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+
+            let addr = u32::from_be_bytes(addr) as usize;
+            if addr & (BLOCK_SIZE - 1) != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("misaligned block address 0x{addr:08x}"),
+                ));
+            }
+
+            let mut block = [0u8; BLOCK_SIZE];
+            reader.read_exact(&mut block)?;
+            blocks.push((addr, block));
+        }
+
+        Ok(blocks)
+    }
+
+    /// The end of an access of `len` bytes at `addr`, erroring if it would
+    /// run past `ADDR_MEM_MAX`.
+    fn access_end(addr: usize, len: usize) -> io::Result<usize> {
+        addr.checked_add(len)
+            .filter(|end| *end <= ADDR_MEM_MAX)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("access of {len} bytes at 0x{addr:x} runs past the end of memory"),
+                )
+            })
+    }
+
+    /// Get all of the block addresses that contain the start address and the size.
+    /// An empty range is in no blocks.
+    fn block_addrs(&self, start_addr: usize, size: usize) -> Vec<usize> {
+        if size == 0 {
+            return vec![];
+        }
+
+        let end = start_addr.saturating_add(size);
+
+        std::iter::successors(Some(start_addr / BLOCK_SIZE * BLOCK_SIZE), |addr| {
+            addr.checked_add(BLOCK_SIZE).filter(|next| *next < end)
+        })
+        .collect()
     }
 
     pub fn read_view(&self, addr: usize, buf: &mut [u8]) -> io::Result<usize> {
         let len = buf.len();
+        if len == 0 {
+            return Ok(0);
+        }
+
+        Self::access_end(addr, len)?;
         let mut read = 0;
 
-        for base_addr in self.block_addrs(addr, len) {
-            if base_addr > addr && read < base_addr - addr {
-                read = base_addr - addr;
-            }
-            let local_addr = addr.saturating_sub(base_addr);
-
-            if let Some(block) = self.tree.get(&base_addr) {
-                let slice = &block[local_addr..(local_addr + len - read).min(BLOCK_SIZE)];
-                let (_, buf_slice) = buf.split_at_mut(read);
-                let (left, _) = buf_slice.split_at_mut(slice.len());
-                left.copy_from_slice(slice);
-                read += slice.len();
-            } else {
-                let (_, buf_slice) = buf.split_at_mut(read);
-                let (left, _) = buf_slice.split_at_mut((len - read).min(BLOCK_SIZE));
-                left.iter_mut().for_each(|m| *m = 0);
-                read += left.len();
+        // copy one block's worth at a time, zero-filling unallocated blocks
+        while read < len {
+            let base_addr = (addr + read) / BLOCK_SIZE * BLOCK_SIZE;
+            let local_addr = addr + read - base_addr;
+            let count = (BLOCK_SIZE - local_addr).min(len - read);
+            let dest = &mut buf[read..read + count];
+
+            match self.tree.get(&base_addr) {
+                Some(block) => dest.copy_from_slice(&block[local_addr..local_addr + count]),
+                None => dest.fill(0),
             }
+
+            read += count;
         }
 
         Ok(len)
@@ -112,6 +320,12 @@ impl Write for Memory {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let addr = self.pos;
         let len = buf.len();
+        // an empty write touches no blocks and records no writers
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let end = Self::access_end(addr, len)?;
         let mut written = 0;
 
         for base_addr in self.block_addrs(addr, len) {
@@ -129,6 +343,12 @@ impl Write for Memory {
             written += slice.len();
         }
 
+        if let (Some(pc), Some(writers)) = (self.writer, &mut self.writers) {
+            for word in (addr & !3..end).step_by(4) {
+                writers.insert(word, pc);
+            }
+        }
+
         self.pos += written;
         Ok(written)
     }
@@ -138,3 +358,81 @@ impl Write for Memory {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_at_end_of_memory() {
+        let mut mem = Memory::new();
+        let mut buf = [0u8; 4];
+
+        assert!(mem.read_view(ADDR_MEM_MAX - 2, &mut buf).is_err());
+        assert_eq!(mem.read_view(ADDR_MEM_MAX - 2, &mut buf[..2]).unwrap(), 2);
+        assert!(mem.read_view(usize::MAX, &mut buf).is_err());
+
+        mem.set_pos(ADDR_MEM_MAX - 2);
+        assert!(mem.write(&[1, 2, 3, 4]).is_err());
+        assert_eq!(mem.write(&[1, 2]).unwrap(), 2);
+
+        mem.read_view(ADDR_MEM_MAX - 4, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn restore_forgets_writers() {
+        let mut mem = Memory::new();
+        mem.set_track_writers(true);
+        mem.writer = Some(ADDR_TEXT);
+        mem.set_pos(ADDR_STATIC);
+        mem.write_all(&[1; 4]).unwrap();
+        mem.set_pos(ADDR_STATIC + BLOCK_SIZE);
+        mem.write_all(&[2; 4]).unwrap();
+        let snapshot = mem.snapshot();
+
+        mem.restore(snapshot[..1].to_vec());
+        assert_eq!(
+            mem.writers(ADDR_STATIC..ADDR_MEM_MAX).collect::<Vec<_>>(),
+            [(ADDR_STATIC + BLOCK_SIZE, ADDR_TEXT)]
+        );
+    }
+
+    #[test]
+    fn read_across_block_boundary() {
+        let mut mem = Memory::new();
+        let pattern = (1..=16).collect::<Vec<u8>>();
+        mem.set_pos(ADDR_STATIC + 250);
+        mem.write_all(&pattern).unwrap();
+
+        let mut buf = [0u8; 32];
+        assert_eq!(mem.read_view(ADDR_STATIC + 240, &mut buf).unwrap(), 32);
+        assert_eq!(buf[..10], [0; 10]);
+        assert_eq!(buf[10..26], pattern[..]);
+        assert_eq!(buf[26..], [0; 6]);
+    }
+
+    #[test]
+    fn read_from_unallocated_into_allocated_block() {
+        let mut mem = Memory::new();
+        mem.set_pos(ADDR_STATIC + BLOCK_SIZE);
+        mem.write_all(&[1, 2, 3, 4]).unwrap();
+
+        let mut buf = [0xffu8; 8];
+        mem.read_view(ADDR_STATIC + BLOCK_SIZE - 4, &mut buf)
+            .unwrap();
+        assert_eq!(buf, [0, 0, 0, 0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn zero_length_access() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.read_view(ADDR_STATIC, &mut []).unwrap(), 0);
+        assert_eq!(mem.read_view(ADDR_MEM_MAX, &mut []).unwrap(), 0);
+
+        mem.set_pos(ADDR_STATIC);
+        assert_eq!(mem.write(&[]).unwrap(), 0);
+        assert_eq!(mem.pos(), ADDR_STATIC);
+        assert!(mem.snapshot().is_empty());
+    }
+}
@@ -1,13 +1,17 @@
+mod exception;
 mod io;
-mod load;
 mod memory;
 mod processor;
 mod registers;
+mod settings;
 mod spawn;
+mod trace;
 
+pub use exception::*;
 pub use io::*;
-pub use load::*;
 pub use memory::*;
 pub use processor::*;
 pub use registers::*;
+pub use settings::*;
 pub use spawn::*;
+pub use trace::*;
@@ -1,14 +1,26 @@
-use std::{io, mem::transmute, num::ParseIntError, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    io::{self, Write},
+    mem::transmute,
+    num::ParseIntError,
+    sync::Arc,
+};
 
 use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use instant::SystemTime;
 use parking_lot::RwLock;
 use thiserror::Error;
 
-use crate::assembler::inst::{Inst, InstType, INST_OPCODE_FUNC};
+use crate::assembler::{
+    disasm::{self, DecodedInstruction},
+    inst::{Inst, InstType},
+    AssembledProgram,
+};
 
 use super::{
-    registers::Registers, AppMessage, AppTx, Memory, ProcRx, ProcSync, RegSync, ADDR_TEXT, REG_A0,
-    REG_V0,
+    registers::Registers, AppMessage, AppTx, ClockMode, Cp0, Exception, Memory, ProcMessage,
+    ProcRx, ProcSettings, ProcSync, RegSync, Register, TraceEntry, ADDR_MEM_MAX, ADDR_STATIC,
+    ADDR_TEXT, DEFAULT_MAX_STEPS, REG_A0, REG_A1, REG_SP, REG_V0, TRACE_LIMIT,
 };
 
 #[allow(clippy::enum_variant_names)]
@@ -18,8 +30,17 @@ pub enum ExecError {
     IoError(#[from] io::Error),
     #[error("io recv error")]
     IoRecvError,
+    /// A read syscall found no input in a build without threads, which can't
+    /// block for it. The syscall is retried once input arrives.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    #[error("waiting for input")]
+    AwaitingInput,
     #[error("int parse error: {0}")]
     IntParseError(#[from] ParseIntError),
+    #[error("invalid random range upper bound {0}")]
+    InvalidRandomBound(i32),
+    #[error("unhandled exception at 0x{1:08x}: {0}")]
+    Unhandled(Exception, usize),
 }
 
 #[derive(Debug)]
@@ -35,12 +56,94 @@ pub struct Processor {
     /// The program counter. Next address to execute.
     pub pc: usize,
 
+    /// The high word of the last multiplication.
+    pub hi: u32,
+
+    /// The low word of the last multiplication.
+    pub lo: u32,
+
+    /// The coprocessor 0 registers, set when an exception is raised.
+    pub cp0: Cp0,
+
+    /// The address control is transferred to when an exception is raised,
+    /// i.e. the start of the kernel text. If the loaded program has no kernel
+    /// text, exceptions halt the processor instead.
+    pub handler: Option<usize>,
+
     /// Whether or not the processor is currently loaded.
     pub loaded: bool,
 
+    /// A map of PC address to source line of the loaded program.
+    pub addr_lines: HashMap<usize, u32>,
+
+    /// The exit code of the program, once it has exited.
+    pub exit_code: Option<i32>,
+
     /// Whether or not the processor is currently active (i.e., executing).
     pub active: bool,
 
+    /// Whether or not the processor is in Run mode, stepping continuously
+    /// until it is stopped.
+    pub running: bool,
+
+    /// Addresses that halt Run mode when written to.
+    pub watchpoints: BTreeSet<usize>,
+
+    /// The target of a taken branch waiting for its delay slot to execute,
+    /// in delay slot mode.
+    delayed_branch: Option<usize>,
+
+    /// An internal breakpoint that halts Run mode once, as an address and
+    /// the `$sp` it is hit at or above, e.g. the return of a call being
+    /// stepped over, but not of recursive calls inside it.
+    pub temp_breakpoint: Option<(usize, u32)>,
+
+    /// The processor settings.
+    pub settings: ProcSettings,
+
+    /// The time of the fixed clock, in milliseconds.
+    pub clock: u64,
+
+    /// The number of instructions executed since the last reset.
+    pub inst_count: u64,
+
+    /// The instruction count when Run mode last started, to enforce the
+    /// maximum steps of a run.
+    pub run_start: u64,
+
+    /// The addresses of the instructions executed since the last reset, if
+    /// coverage is enabled.
+    pub executed: HashSet<usize>,
+
+    /// The executed addresses not yet synced with the app.
+    executed_diff: Vec<usize>,
+
+    /// The random number generator used by the random syscalls.
+    pub rng: XorShift,
+
+    /// The most recent steps executed, if tracing is enabled.
+    pub trace: VecDeque<TraceEntry>,
+
+    /// Input received but not yet read by the read-char syscall.
+    pub input_chars: VecDeque<char>,
+
+    /// Input messages received while no read syscall was waiting, in a build
+    /// without threads.
+    #[cfg(target_arch = "wasm32")]
+    pub pending_input: VecDeque<ProcMessage>,
+
+    /// A step message stopped by a read syscall waiting for input, retried
+    /// once input is sent, in a build without threads.
+    #[cfg(target_arch = "wasm32")]
+    pub awaiting_step: Option<ProcMessage>,
+
+    /// Other messages received while a read syscall waited for input, handled
+    /// once the read returns.
+    pub deferred: VecDeque<ProcMessage>,
+
+    /// The store made by the step being traced.
+    last_store: Option<(usize, usize, u32)>,
+
     /// The app message transmitter.
     pub app_tx: AppTx,
 
@@ -48,6 +151,50 @@ pub struct Processor {
     pub proc_rx: ProcRx,
 }
 
+/// A small xorshift pseudo-random number generator.
+#[derive(Debug, Clone)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // the state must never be zero
+        Self(if seed == 0 { 0x9e3779b97f4a7c15 } else { seed })
+    }
+
+    /// Seed a generator from the system time.
+    pub fn from_time() -> Self {
+        Self::new(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 32) as u32
+    }
+}
+
+/// The access width in bytes of a load or store opcode, and whether or not
+/// it is a store.
+fn access_width(opcode: u8) -> Option<(i64, bool)> {
+    match opcode {
+        0x24 => Some((1, false)),
+        0x25 => Some((2, false)),
+        0x23 => Some((4, false)),
+        0x28 => Some((1, true)),
+        0x29 => Some((2, true)),
+        0x2b => Some((4, true)),
+        _ => None,
+    }
+}
+
 #[inline]
 fn to_signed_imm(imm: u16) -> i16 {
     unsafe { transmute(imm) }
@@ -59,8 +206,33 @@ impl Processor {
             regs: Registers::default(),
             mem: Arc::new(RwLock::new(Memory::new())),
             pc: ADDR_TEXT,
+            hi: 0,
+            lo: 0,
+            cp0: Cp0::default(),
+            handler: None,
             loaded: false,
+            addr_lines: HashMap::new(),
+            exit_code: None,
             active: false,
+            running: false,
+            watchpoints: BTreeSet::new(),
+            delayed_branch: None,
+            temp_breakpoint: None,
+            settings: ProcSettings::default(),
+            clock: 0,
+            inst_count: 0,
+            run_start: 0,
+            executed: HashSet::new(),
+            executed_diff: Vec::new(),
+            rng: XorShift::from_time(),
+            trace: VecDeque::new(),
+            input_chars: VecDeque::new(),
+            #[cfg(target_arch = "wasm32")]
+            pending_input: VecDeque::new(),
+            #[cfg(target_arch = "wasm32")]
+            awaiting_step: None,
+            deferred: VecDeque::new(),
+            last_store: None,
             app_tx,
             proc_rx,
         }
@@ -72,16 +244,136 @@ impl Processor {
 
     pub fn reset(&mut self) -> ProcSync {
         self.mem.write().reset();
+        self.addr_lines.clear();
+        self.reset_state()
+    }
+
+    /// Reset the registers and PC to rerun the loaded program. Memory,
+    /// including any data the program changed, is kept as is.
+    pub fn restart(&mut self) -> ProcSync {
+        let (loaded, handler) = (self.loaded, self.handler);
+        self.reset_state();
+        self.loaded = loaded;
+        self.active = loaded;
+        self.handler = handler;
+
+        self.sync_hard()
+    }
+
+    /// Reset everything but memory.
+    fn reset_state(&mut self) -> ProcSync {
         self.regs = Registers::default();
+        for (i, value) in self.settings.initial_args.iter().enumerate() {
+            self.regs.data[REG_A0 as usize + i] = Register(*value as i32);
+        }
+        self.regs.data[REG_SP as usize] = Register(self.settings.initial_sp as i32);
         self.pc = ADDR_TEXT;
+        self.hi = 0;
+        self.lo = 0;
+        self.cp0 = Cp0::default();
+        self.handler = None;
         self.loaded = false;
+        self.exit_code = None;
         self.active = false;
+        self.running = false;
+        self.delayed_branch = None;
+        self.temp_breakpoint = None;
+        self.clock = 0;
+        self.inst_count = 0;
+        self.executed.clear();
+        self.executed_diff.clear();
+        self.trace.clear();
+        self.input_chars.clear();
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.pending_input.clear();
+            self.awaiting_step = None;
+        }
+        self.rng = match self.settings.seed {
+            Some(seed) => XorShift::new(seed),
+            None => XorShift::from_time(),
+        };
 
         ProcSync {
             pc: self.pc,
             regs: RegSync::Set(self.regs.data),
+            hi: self.hi,
+            lo: self.lo,
+            cp0: self.cp0,
+            loaded: self.loaded,
             active: self.active,
+            running: self.running,
+            inst_count: self.inst_count,
+            executed: vec![],
+        }
+    }
+
+    /// Reset the processor and load an assembled program into memory.
+    pub fn load(&mut self, program: &AssembledProgram) -> io::Result<ProcSync> {
+        self.reset();
+
+        {
+            let mut mem = self.mem.write();
+
+            mem.set_pos(ADDR_TEXT);
+            for word in program.text.iter() {
+                mem.write_u32::<BE>(*word)?;
+            }
+
+            mem.set_pos(ADDR_STATIC);
+            mem.write_all(&program.data)?;
+
+            mem.set_pos(program.ktext_addr);
+            for word in program.ktext.iter() {
+                mem.write_u32::<BE>(*word)?;
+            }
+
+            mem.set_pos(program.kdata_addr);
+            mem.write_all(&program.kdata)?;
         }
+
+        self.handler = (!program.ktext.is_empty()).then_some(program.ktext_addr);
+        self.addr_lines = program.addr_lines.clone();
+        self.active = true;
+        self.loaded = true;
+
+        Ok(self.sync_hard())
+    }
+
+    /// Reset the processor and replace the text segments with those of an
+    /// assembled program. The rest of memory, including the static data,
+    /// heap and stack, is preserved; the program's own data is not loaded.
+    pub fn reload_code(&mut self, program: &AssembledProgram) -> io::Result<ProcSync> {
+        self.reset_state();
+
+        {
+            let mut mem = self.mem.write();
+            mem.writer = None;
+            mem.clear(ADDR_TEXT..ADDR_STATIC);
+
+            mem.set_pos(ADDR_TEXT);
+            for word in program.text.iter() {
+                mem.write_u32::<BE>(*word)?;
+            }
+
+            mem.set_pos(program.ktext_addr);
+            for word in program.ktext.iter() {
+                mem.write_u32::<BE>(*word)?;
+            }
+        }
+
+        self.handler = (!program.ktext.is_empty()).then_some(program.ktext_addr);
+        self.addr_lines = program.addr_lines.clone();
+        self.active = true;
+        self.loaded = true;
+
+        Ok(self.sync_hard())
+    }
+
+    /// Apply new processor settings.
+    pub fn set_settings(&mut self, settings: ProcSettings) {
+        self.mem.write().set_track_writers(settings.track_writers);
+        self.settings = settings;
     }
 
     /// Generate a processor sync context that the app
@@ -90,44 +382,371 @@ impl Processor {
         ProcSync {
             pc: self.pc,
             regs: RegSync::Diff(std::mem::take(&mut self.regs.diff)),
+            hi: self.hi,
+            lo: self.lo,
+            cp0: self.cp0,
+            loaded: self.loaded,
             active: self.active,
+            running: self.running,
+            inst_count: self.inst_count,
+            executed: std::mem::take(&mut self.executed_diff),
         }
     }
 
+    /// All of the executed addresses, for a hard sync.
+    fn take_executed(&mut self) -> Vec<usize> {
+        self.executed_diff.clear();
+        self.executed.iter().copied().collect()
+    }
+
     /// Generate a hard-sync processor sync context.
     /// Will force setting over diffing.
     pub fn sync_hard(&mut self) -> ProcSync {
         ProcSync {
             pc: self.pc,
             regs: RegSync::Set(self.regs.data),
+            hi: self.hi,
+            lo: self.lo,
+            cp0: self.cp0,
+            loaded: self.loaded,
             active: self.active,
+            running: self.running,
+            inst_count: self.inst_count,
+            executed: self.take_executed(),
+        }
+    }
+
+    /// Decode the instruction at `addr`, if it is a known instruction.
+    pub fn inst_at(&self, addr: usize) -> io::Result<Option<&'static Inst>> {
+        let mut buf = [0u8; 4];
+        self.mem.read().read_view(addr, &mut buf)?;
+
+        Ok(disasm::decode(u32::from_be_bytes(buf)))
+    }
+
+    /// The address a subroutine call at the PC returns to.
+    pub fn return_addr(&self) -> usize {
+        if self.settings.delay_slots {
+            self.pc + 8
+        } else {
+            self.pc + 4
+        }
+    }
+
+    /// Whether or not the next instruction to execute is a subroutine call.
+    pub fn next_is_call(&self) -> bool {
+        matches!(self.inst_at(self.pc), Ok(Some(inst)) if inst.mnemonic == "jal")
+    }
+
+    /// Called before a store of `len` bytes at `addr`. Records the store for
+    /// the execution trace, and if it touches a watchpoint, Run mode is halted
+    /// and the write is logged.
+    fn on_store(&mut self, addr: usize, len: usize, new: u32) -> io::Result<()> {
+        self.last_store = Some((addr, len, new));
+
+        if self.watchpoints.range(addr..addr + len).next().is_none() {
+            return Ok(());
         }
+
+        let mut buf = [0u8; 4];
+        self.mem.read().read_view(addr, &mut buf[..len])?;
+        let old = buf[..len]
+            .iter()
+            .fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+
+        self.running = false;
+        let _ = self.app_tx.send(AppMessage::Log(format!(
+            "Watchpoint hit at 0x{addr:08x}: 0x{old:0width$x} -> 0x{new:0width$x} (PC 0x{:08x})",
+            self.pc,
+            width = len * 2,
+        )));
+
+        Ok(())
     }
 
+    /// Step the processor, recording the step in the execution trace if
+    /// tracing is enabled.
     pub fn step(&mut self) -> Result<(), ExecError> {
+        if !self.settings.trace {
+            return self.execute();
+        }
+
+        let pc = self.pc;
+        let mut word = [0u8; 4];
+        self.mem.read().read_view(pc, &mut word)?;
+        let before = self.regs.data;
+        self.last_store = None;
+
+        let result = self.execute();
+        if let Err(ExecError::AwaitingInput) = result {
+            return result;
+        }
+
+        if self.trace.len() >= TRACE_LIMIT {
+            self.trace.pop_front();
+        }
+
+        self.trace.push_back(TraceEntry {
+            pc,
+            word: u32::from_be_bytes(word),
+            regs: (0..32)
+                .filter(|&i| self.regs.data[i] != before[i])
+                .map(|i| (i, self.regs.data[i].0))
+                .collect(),
+            store: self.last_store,
+        });
+
+        result
+    }
+
+    /// Execute instruction words as if they were at the PC, e.g. typed into
+    /// the console, returning a description of what they changed. The PC
+    /// only moves if an instruction jumps or branches, and exits and
+    /// unhandled exceptions don't end the loaded program. Branches land at
+    /// once, even with delay slots, and keep any branch pending in the
+    /// program.
+    pub fn execute_words(&mut self, words: &[u32]) -> Result<String, ExecError> {
+        let active = self.active;
+        let before = (self.regs.data, self.hi, self.lo, self.pc);
+        let delayed = self.delayed_branch.take();
+        let mut stores = vec![];
+        self.mem.write().writer = None;
+
+        for word in words {
+            let pc = self.pc;
+            self.last_store = None;
+
+            // a jump to the next instruction still moves the PC
+            let fields = disasm::decode_fields(*word);
+            let jump = matches!(fields.opcode, 0x02 | 0x03)
+                || fields.opcode == 0x00 && matches!(fields.func, 0x08 | 0x09);
+
+            let result = self.execute_word(*word);
+            let target = self.delayed_branch.take();
+            if result.is_err() || (self.pc == pc + 4 && !jump) {
+                self.pc = pc;
+            }
+            if let Some(target) = target.filter(|_| result.is_ok()) {
+                self.pc = target;
+            }
+            self.active = active;
+            if result.is_err() {
+                self.delayed_branch = delayed;
+            }
+            result?;
+
+            stores.extend(self.last_store);
+        }
+        self.delayed_branch = delayed;
+
+        let mut changes = self.reg_changes(&before.0, before.1, before.2);
+        for (addr, len, new) in stores {
+            changes.push(format!(
+                "[0x{addr:08x}] = 0x{new:0width$x}",
+                width = len * 2
+            ));
+        }
+        if self.pc != before.3 {
+            changes.push(format!("PC = 0x{:08x}", self.pc));
+        }
+
+        if changes.is_empty() {
+            return Ok("no changes".to_owned());
+        }
+
+        Ok(changes.join(", "))
+    }
+
+    /// Describe the registers, hi and lo that differ from the given values.
+    fn reg_changes(&self, regs: &[Register; 32], hi: u32, lo: u32) -> Vec<String> {
+        let mut changes = (0..32)
+            .filter(|&i| self.regs.data[i] != regs[i])
+            .map(|i| {
+                let value = self.regs.data[i];
+                format!(
+                    "${} = 0x{:08x} ({})",
+                    Registers::name(i),
+                    value.to_u32(),
+                    value.0
+                )
+            })
+            .collect::<Vec<_>>();
+        for (name, old, new) in [("hi", hi, self.hi), ("lo", lo, self.lo)] {
+            if old != new {
+                changes.push(format!("{name} = 0x{new:08x}"));
+            }
+        }
+
+        changes
+    }
+
+    /// Run a program, e.g. a selection of the editor, in a throwaway
+    /// processor with its own memory, starting from the registers of this
+    /// one. It runs until it exits, errors, leaves its text segment or hits
+    /// the step limit, and the returned description lists the registers it
+    /// changed and anything it printed. Input syscalls fail, as nothing can
+    /// send it input, and an unlimited step limit falls back to the default.
+    ///
+    /// Between batches of steps it checks for [`ProcMessage::Stop`], so a long
+    /// run can be stopped. Any other messages received meanwhile are pushed
+    /// to `deferred`, to be handled once it returns.
+    pub fn run_isolated(
+        &self,
+        program: &AssembledProgram,
+        deferred: &mut Vec<ProcMessage>,
+    ) -> Result<String, ExecError> {
+        let (app_tx, app_rx) = crossbeam::channel::unbounded();
+        let (_, proc_rx) = crossbeam::channel::unbounded();
+
+        let mut scratch = Self::new(app_tx, proc_rx);
+        scratch.set_settings(ProcSettings {
+            trace: false,
+            ..self.settings.clone()
+        });
+        scratch.load(program)?;
+        scratch.regs.data = self.regs.data;
+        scratch.hi = self.hi;
+        scratch.lo = self.lo;
+
+        let text = ADDR_TEXT..ADDR_TEXT + program.text.len() * 4;
+        let max_steps = match self.settings.max_steps {
+            0 => DEFAULT_MAX_STEPS,
+            max_steps => max_steps,
+        };
+        let batch = self.settings.run_batch.max(1) as u64;
+        let mut error = None;
+        let mut stopped = false;
+        while scratch.active && text.contains(&scratch.pc) {
+            if scratch.inst_count >= max_steps {
+                break;
+            }
+            if scratch.inst_count % batch == 0 {
+                for message in self.proc_rx.try_iter() {
+                    match message {
+                        ProcMessage::Stop => stopped = true,
+                        message => deferred.push(message),
+                    }
+                }
+                if stopped {
+                    break;
+                }
+            }
+            if let Err(e) = scratch.step() {
+                error = Some(e);
+                break;
+            }
+        }
+
+        let ended = match (&error, scratch.exit_code) {
+            (Some(e), _) => format!("stopped with an error: {e}"),
+            (None, Some(code)) => format!("exited with code {code}"),
+            (None, None) if stopped => "stopped".to_owned(),
+            (None, None) if scratch.pc == text.end => "ran to the end".to_owned(),
+            (None, None) if text.contains(&scratch.pc) => {
+                format!("stopped after reaching the limit of {max_steps} instructions")
+            }
+            (None, None) => format!("left the selection for 0x{:08x}", scratch.pc),
+        };
+        let mut lines = vec![format!(
+            "Ran {} instruction{}, {ended}",
+            scratch.inst_count,
+            if scratch.inst_count == 1 { "" } else { "s" },
+        )];
+
+        let changes = scratch.reg_changes(&self.regs.data, self.hi, self.lo);
+        if changes.is_empty() {
+            lines.push("no register changes".to_owned());
+        } else {
+            lines.push(changes.join(", "));
+        }
+
+        let mut output = String::new();
+        for message in app_rx.try_iter() {
+            match message {
+                AppMessage::Io(text) => output += &text,
+                AppMessage::Log(text) => lines.push(text),
+                _ => (),
+            }
+        }
+        if !output.is_empty() {
+            lines.push(format!("output: {output:?}"));
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    /// Write the execution trace, one line per step.
+    pub fn write_trace<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for entry in self.trace.iter() {
+            writeln!(writer, "{entry}")?;
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<(), ExecError> {
+        let pc = self.pc;
+
+        // a branch taken by the previous instruction lands once this one, its
+        // delay slot, has run straight through
+        let delayed = self.delayed_branch.take();
+        let result = self.execute_inst();
+        if let Err(ExecError::AwaitingInput) = result {
+            // nothing ran, so it is retried as is
+            self.delayed_branch = delayed;
+            return result;
+        }
+        if result.is_ok() {
+            self.inst_count += 1;
+        }
+        if self.settings.coverage && self.executed.insert(pc) {
+            self.executed_diff.push(pc);
+        }
+        if let Some(target) = delayed.filter(|_| self.pc == pc + 4) {
+            self.pc = target;
+        }
+
+        result
+    }
+
+    /// Transfer control to `target`. In delay slot mode the branch is delayed
+    /// until the next instruction has executed. Returns whether or not the PC
+    /// was set.
+    fn branch(&mut self, target: usize) -> bool {
+        if self.settings.delay_slots {
+            self.delayed_branch = Some(target);
+            false
+        } else {
+            self.pc = target;
+            true
+        }
+    }
+
+    fn execute_inst(&mut self) -> Result<(), ExecError> {
         // TODO: use the UI logging
 
         let data = {
             let mut lock = self.mem.write();
+            lock.writer = Some(self.pc);
             lock.set_pos(self.pc);
             lock.read_u32::<BE>()?
         };
 
-        let opcode = (data >> 26) as u8;
+        self.execute_word(data)
+    }
+
+    /// Execute an instruction word as if it were at the PC.
+    fn execute_word(&mut self, data: u32) -> Result<(), ExecError> {
+        let fields = disasm::decode_fields(data);
+        let inst = match disasm::decode_inst(&fields) {
+            Some(inst) => inst,
+            None => return self.raise(Exception::ReservedInstruction(data)),
+        };
 
-        match opcode {
+        match fields.opcode {
             // R-type
             0x00 => {
-                let func = (data & 0x3f) as u8;
-                let inst = match INST_OPCODE_FUNC.get(&(0x00, func)) {
-                    Some(inst) => inst,
-                    None => {
-                        println!("unknown R-type func {func}");
-                        return Ok(());
-                    }
-                };
-
-                match func {
+                match fields.func {
                     0x0c => {
                         match self.regs.get_u32(REG_V0) {
                             // print integer
@@ -165,55 +784,115 @@ impl Processor {
 
                             // read int
                             5 => {
-                                let input = self.io_recv().map_err(|_| ExecError::IoRecvError)?;
+                                let input = self.io_recv()?;
                                 let parsed = str::parse::<i32>(&input)?;
                                 self.regs.set_i32(REG_V0, parsed);
                             }
 
-                            code => {
-                                println!("unimplemented syscall {code}");
+                            // exit
+                            10 => {
+                                self.active = false;
+                                self.running = false;
+                                self.exit_code = Some(0);
+                                let _ = self
+                                    .app_tx
+                                    .send(AppMessage::Log("Program exited".to_string()));
+                            }
+
+                            // read char
+                            12 => {
+                                let c = self.char_recv()?;
+                                self.regs.set_u32(REG_V0, c as u32);
+                            }
+
+                            // exit with code
+                            17 => {
+                                let code = self.regs.get_i32(REG_A0);
+                                self.active = false;
+                                self.running = false;
+                                self.exit_code = Some(code);
+                                let _ = self.app_tx.send(AppMessage::Log(format!(
+                                    "Program exited with code {code}"
+                                )));
+                            }
+
+                            // system time
+                            30 => {
+                                let time = match self.settings.clock {
+                                    ClockMode::System => SystemTime::now()
+                                        .duration_since(SystemTime::UNIX_EPOCH)
+                                        .map(|d| d.as_millis() as u64)
+                                        .unwrap_or(0),
+                                    ClockMode::Fixed(step) => {
+                                        self.clock = self.clock.wrapping_add(step);
+                                        self.clock
+                                    }
+                                };
+
+                                self.regs.set_u32(REG_A0, time as u32);
+                                self.regs.set_u32(REG_A1, (time >> 32) as u32);
+                            }
+
+                            // set seed
+                            40 => {
+                                self.rng = XorShift::new(self.regs.get_u32(REG_A1) as u64);
+                            }
+
+                            // random int
+                            41 => {
+                                let value = self.rng.next_u32();
+                                self.regs.set_u32(REG_A0, value);
                             }
+
+                            // random int range
+                            42 => {
+                                let bound = self.regs.get_i32(REG_A1);
+                                if bound <= 0 {
+                                    return Err(ExecError::InvalidRandomBound(bound));
+                                }
+
+                                let value = self.rng.next_u32() % bound as u32;
+                                self.regs.set_u32(REG_A0, value);
+                            }
+
+                            code => return self.raise(Exception::Syscall(code)),
                         }
                         self.pc += 4;
                     }
-                    _ => self.call_rtype(data, inst)?,
+                    _ => self.call_rtype(&fields, inst)?,
                 }
             }
 
-            // I- or J-type
-            _ => {
-                let inst = match INST_OPCODE_FUNC.get(&(opcode, 0x00)) {
-                    Some(inst) => inst,
-                    None => {
-                        println!("unknown I- or J-type opcode {opcode}");
-                        return Ok(());
-                    }
-                };
+            // coprocessor 0
+            0x10 => self.call_cop0(&fields, inst),
 
-                match inst.ty {
-                    InstType::I | InstType::Ils => self.call_itype(data, inst)?,
-                    InstType::J => self.call_jtype(data, inst)?,
-                    _ => unreachable!(),
-                }
-            }
+            // I- or J-type
+            _ => match inst.ty {
+                InstType::I | InstType::Ils => self.call_itype(&fields, inst)?,
+                InstType::J => self.call_jtype(&fields, inst)?,
+                _ => unreachable!(),
+            },
         }
 
         Ok(())
     }
 
-    pub fn call_rtype(&mut self, encoded: u32, inst: &'static Inst) -> Result<(), ExecError> {
-        let rs = ((encoded >> 21) & 0x1f) as u8;
-        let rt = ((encoded >> 16) & 0x1f) as u8;
-        let rd = ((encoded >> 11) & 0x1f) as u8;
-        let shamt = ((encoded >> 6) & 0x1f) as u8;
+    pub fn call_rtype(
+        &mut self,
+        fields: &DecodedInstruction,
+        inst: &'static Inst,
+    ) -> Result<(), ExecError> {
+        let DecodedInstruction {
+            rs, rt, rd, shamt, ..
+        } = *fields;
         let mut inc_pc = true;
 
         match inst.func {
             // add
-            0x20 => self.regs.set_i32(
-                rd,
-                self.regs.get_i32(rs).wrapping_add(self.regs.get_i32(rt)),
-            ),
+            0x20 => match self.regs.get_i32(rs).checked_add(self.regs.get_i32(rt)) {
+                Some(value) => self.regs.set_i32(rd, value),
+                None => return self.raise(Exception::Overflow),
+            },
 
             // addu
             0x21 => self.regs.set_u32(
@@ -257,26 +936,79 @@ impl Processor {
             ),
 
             // sll
-            0x00 => self.regs.set_u32(rd, self.regs.get_u32(rs) << shamt as u32),
+            0x00 => self.regs.set_u32(rd, self.regs.get_u32(rt) << shamt as u32),
 
             // sra
-            0x03 => self.regs.set_i32(rd, self.regs.get_i32(rs) >> shamt as i32),
+            0x03 => self.regs.set_i32(rd, self.regs.get_i32(rt) >> shamt as i32),
 
             // srl
-            0x02 => self.regs.set_u32(rd, self.regs.get_u32(rs) >> shamt as u32),
+            0x02 => self.regs.set_u32(rd, self.regs.get_u32(rt) >> shamt as u32),
+
+            // sllv
+            0x04 => self
+                .regs
+                .set_u32(rd, self.regs.get_u32(rt) << (self.regs.get_u32(rs) & 0x1f)),
+
+            // srav
+            0x07 => self
+                .regs
+                .set_i32(rd, self.regs.get_i32(rt) >> (self.regs.get_u32(rs) & 0x1f)),
+
+            // srlv
+            0x06 => self
+                .regs
+                .set_u32(rd, self.regs.get_u32(rt) >> (self.regs.get_u32(rs) & 0x1f)),
 
             // sub
-            0x22 => self.regs.set_i32(
-                rd,
-                self.regs.get_i32(rs).wrapping_sub(self.regs.get_i32(rt)),
-            ),
+            0x22 => match self.regs.get_i32(rs).checked_sub(self.regs.get_i32(rt)) {
+                Some(value) => self.regs.set_i32(rd, value),
+                None => return self.raise(Exception::Overflow),
+            },
 
             // subu
             0x23 => self.regs.set_u32(
-                rt,
+                rd,
                 self.regs.get_u32(rs).wrapping_sub(self.regs.get_u32(rt)),
             ),
 
+            // mult
+            0x18 => {
+                let product = self.regs.get_i32(rs) as i64 * self.regs.get_i32(rt) as i64;
+                self.hi = (product >> 32) as u32;
+                self.lo = product as u32;
+            }
+
+            // multu
+            0x19 => {
+                let product = self.regs.get_u32(rs) as u64 * self.regs.get_u32(rt) as u64;
+                self.hi = (product >> 32) as u32;
+                self.lo = product as u32;
+            }
+
+            // div, leaving hi and lo unchanged when dividing by zero
+            0x1a => {
+                let (dividend, divisor) = (self.regs.get_i32(rs), self.regs.get_i32(rt));
+                if divisor != 0 {
+                    self.hi = dividend.wrapping_rem(divisor) as u32;
+                    self.lo = dividend.wrapping_div(divisor) as u32;
+                }
+            }
+
+            // divu
+            0x1b => {
+                let (dividend, divisor) = (self.regs.get_u32(rs), self.regs.get_u32(rt));
+                if divisor != 0 {
+                    self.hi = dividend % divisor;
+                    self.lo = dividend / divisor;
+                }
+            }
+
+            // mfhi
+            0x10 => self.regs.set_u32(rd, self.hi),
+
+            // mflo
+            0x12 => self.regs.set_u32(rd, self.lo),
+
             // xor
             0x26 => self
                 .regs
@@ -284,8 +1016,7 @@ impl Processor {
 
             // jr
             0x08 => {
-                self.pc = (self.regs.get_u32(rs) as usize) << 2;
-                inc_pc = false;
+                inc_pc = !self.branch((self.regs.get_u32(rs) as usize) << 2);
             }
 
             _ => unreachable!(),
@@ -298,20 +1029,33 @@ impl Processor {
         Ok(())
     }
 
-    pub fn call_itype(&mut self, encoded: u32, inst: &'static Inst) -> io::Result<()> {
-        let rs = ((encoded >> 21) & 0x1f) as u8;
-        let rt = ((encoded >> 16) & 0x1f) as u8;
-        let imm = (encoded & 0xffff) as u16;
+    pub fn call_itype(
+        &mut self,
+        fields: &DecodedInstruction,
+        inst: &'static Inst,
+    ) -> Result<(), ExecError> {
+        let DecodedInstruction { rs, rt, imm, .. } = *fields;
         let mut inc_pc = true;
 
+        // loads and stores must be aligned to their width and in range
+        if let Some((width, store)) = access_width(inst.opcode) {
+            let addr = self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64;
+
+            if !(0..ADDR_MEM_MAX as i64).contains(&addr) || addr & (width - 1) != 0 {
+                return self.raise(if store {
+                    Exception::AddressStore(addr as u32)
+                } else {
+                    Exception::AddressLoad(addr as u32)
+                });
+            }
+        }
+
         match inst.opcode {
             // addi
-            0x08 => self.regs.set_i32(
-                rt,
-                self.regs
-                    .get_i32(rs)
-                    .wrapping_add(to_signed_imm(imm) as i32),
-            ),
+            0x08 => match self.regs.get_i32(rs).checked_add(to_signed_imm(imm) as i32) {
+                Some(value) => self.regs.set_i32(rt, value),
+                None => return self.raise(Exception::Overflow),
+            },
 
             // addiu
             0x09 => self
@@ -327,6 +1071,9 @@ impl Processor {
             // ori
             0x0d => self.regs.set_u32(rt, self.regs.get_u32(rs) | imm as u32),
 
+            // xori
+            0x0e => self.regs.set_u32(rt, self.regs.get_u32(rs) ^ imm as u32),
+
             // slti
             0x0a => self.regs.set_u32(
                 rt,
@@ -370,40 +1117,49 @@ impl Processor {
 
             // sb
             0x28 => {
+                let addr = (self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize;
+                self.on_store(addr, 1, self.regs.get_u32(rt) as u8 as u32)?;
+
                 let mut mem = self.mem.write();
-                mem.set_pos((self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize);
+                mem.set_pos(addr);
                 mem.write_u8(self.regs.get_u32(rt) as u8)?;
             }
 
             // sh
             0x29 => {
+                let addr = (self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize;
+                self.on_store(addr, 2, self.regs.get_u32(rt) as u16 as u32)?;
+
                 let mut mem = self.mem.write();
-                mem.set_pos((self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize);
+                mem.set_pos(addr);
                 mem.write_u16::<BE>(self.regs.get_u32(rt) as u16)?;
             }
 
             // sw
             0x2b => {
+                let addr = (self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize;
+                self.on_store(addr, 4, self.regs.get_u32(rt))?;
+
                 let mut mem = self.mem.write();
-                mem.set_pos((self.regs.get_u32(rs) as i64 + to_signed_imm(imm) as i64) as usize);
+                mem.set_pos(addr);
                 mem.write_u32::<BE>(self.regs.get_u32(rt))?;
             }
 
             // beq
             0x04 => {
                 if self.regs.get_u32(rt) == self.regs.get_u32(rs) {
-                    inc_pc = false;
-                    self.pc =
-                        (self.pc as isize + 4 + ((to_signed_imm(imm) as isize) << 2)) as usize;
+                    inc_pc = !self.branch(
+                        (self.pc as isize + 4 + ((to_signed_imm(imm) as isize) << 2)) as usize,
+                    );
                 }
             }
 
             // bne
             0x05 => {
                 if self.regs.get_u32(rt) != self.regs.get_u32(rs) {
-                    inc_pc = false;
-                    self.pc =
-                        (self.pc as isize + 4 + ((to_signed_imm(imm) as isize) << 2)) as usize;
+                    inc_pc = !self.branch(
+                        (self.pc as isize + 4 + ((to_signed_imm(imm) as isize) << 2)) as usize,
+                    );
                 }
             }
 
@@ -417,25 +1173,332 @@ impl Processor {
         Ok(())
     }
 
-    pub fn call_jtype(&mut self, encoded: u32, inst: &'static Inst) -> io::Result<()> {
-        let addr = encoded & 0x3ffffff;
+    pub fn call_cop0(&mut self, fields: &DecodedInstruction, inst: &'static Inst) {
+        let DecodedInstruction { rt, rd, .. } = *fields;
+
+        match inst.func {
+            // mfc0
+            0x00 => self.regs.set_u32(rt, self.cp0.get(rd)),
+
+            // mtc0
+            0x04 => self.cp0.set(rd, self.regs.get_u32(rt)),
+
+            // eret
+            0x10 => {
+                self.pc = self.cp0.epc as usize;
+                return;
+            }
+
+            _ => unreachable!(),
+        }
+
+        self.pc += 4;
+    }
+
+    /// Raise an exception at the current PC. If a handler is loaded, the
+    /// cause and EPC are recorded and control transfers to the handler;
+    /// otherwise the processor halts with an error.
+    fn raise(&mut self, exception: Exception) -> Result<(), ExecError> {
+        let Some(handler) = self.handler else {
+            self.active = false;
+            self.running = false;
+            return Err(ExecError::Unhandled(exception, self.pc));
+        };
+
+        if let Some(addr) = exception.bad_addr() {
+            self.cp0.bad_vaddr = addr;
+        }
+
+        self.cp0.cause = exception.code() << 2;
+        self.cp0.epc = self.pc as u32;
+        self.pc = handler;
+
+        Ok(())
+    }
+
+    pub fn call_jtype(
+        &mut self,
+        fields: &DecodedInstruction,
+        inst: &'static Inst,
+    ) -> io::Result<()> {
+        let addr = fields.addr;
 
         match inst.opcode {
             // j
-            0x02 => {
-                self.pc = (addr as usize) << 2;
-            }
+            0x02 => {}
 
             // jal
             0x03 => {
-                // set ra to the current pc
-                self.regs.set_u32(31, (self.pc >> 2) as u32 + 1);
-                self.pc = (addr as usize) << 2;
+                // set ra to the instruction to return to, past any delay slot
+                let ret = if self.settings.delay_slots { 2 } else { 1 };
+                self.regs.set_u32(31, (self.pc >> 2) as u32 + ret);
             }
 
             _ => unreachable!(),
         }
 
+        if !self.branch((addr as usize) << 2) {
+            self.pc += 4;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::assemble,
+        simulator::{REG_T0, REG_T1},
+    };
+
+    /// Assemble `source` into a new processor, returning it with the size of
+    /// its text segment.
+    fn load(source: &str) -> (Processor, usize) {
+        let (app_tx, _) = crossbeam::channel::unbounded();
+        let (_, proc_rx) = crossbeam::channel::unbounded();
+        let mut proc = Processor::new(app_tx, proc_rx);
+        let program = assemble(source, &Default::default()).unwrap();
+        proc.load(&program).unwrap();
+
+        (proc, program.text.len() * 4)
+    }
+
+    /// Assemble and run `source` until it leaves the text segment, returning
+    /// the processor and the first error, if any.
+    fn run(source: &str) -> (Processor, Option<ExecError>) {
+        let (mut proc, len) = load(source);
+        let text = ADDR_TEXT..ADDR_TEXT + len;
+        while proc.active && text.contains(&proc.pc) {
+            if let Err(e) = proc.step() {
+                return (proc, Some(e));
+            }
+        }
+
+        (proc, None)
+    }
+
+    #[test]
+    fn abs() {
+        for (value, expected) in [(-5, 5), (5, 5), (0, 0), (i32::MIN, i32::MIN)] {
+            let (proc, error) = run(&format!("li $t0, {value}\nabs $t1, $t0\n"));
+            assert!(error.is_none());
+            assert_eq!(proc.regs.get_i32(REG_T1), expected);
+        }
+    }
+
+    #[test]
+    fn mul() {
+        let (proc, error) = run("li $t0, -6\nli $t1, 7\nmul $t0, $t0, $t1\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_i32(REG_T0), -42);
+    }
+
+    #[test]
+    fn subu_wraps() {
+        let (proc, error) = run("li $t0, 1\nli $t1, 2\nsubu $t0, $t0, $t1\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T0), u32::MAX);
+        assert_eq!(proc.regs.get_u32(REG_T1), 2);
+    }
+
+    #[test]
+    fn unknown_instruction_raises() {
+        // an unused R-type function, then an unused opcode
+        for word in [0x0000003f, 0xfc000000] {
+            let (mut proc, _) = load("nop\n");
+            {
+                let mut mem = proc.mem.write();
+                mem.set_pos(ADDR_TEXT);
+                mem.write_u32::<BE>(word).unwrap();
+            }
+
+            let error = proc.step().unwrap_err();
+            assert!(matches!(
+                error,
+                ExecError::Unhandled(Exception::ReservedInstruction(w), ADDR_TEXT) if w == word
+            ));
+            assert!(!proc.active);
+            assert_eq!(proc.pc, ADDR_TEXT);
+        }
+    }
+
+    #[test]
+    fn console_branch_with_delay_slots() {
+        let (mut proc, _) = load("j end\nnop\nnop\nend: nop\n");
+        proc.settings.delay_slots = true;
+        let jump = assemble(&format!("j 0x{:08x}\n", ADDR_TEXT + 8), &Default::default())
+            .unwrap()
+            .text[0];
+
+        // a console jump lands at once and leaves no branch behind
+        proc.execute_words(&[jump]).unwrap();
+        assert_eq!(proc.pc, ADDR_TEXT + 8);
+        proc.step().unwrap();
+        assert_eq!(proc.pc, ADDR_TEXT + 12);
+
+        // a branch pending in the program survives the console
+        proc.pc = ADDR_TEXT;
+        proc.step().unwrap();
+        proc.execute_words(&[jump]).unwrap();
+        proc.pc = ADDR_TEXT + 4;
+        proc.step().unwrap();
+        assert_eq!(proc.pc, ADDR_TEXT + 12);
+    }
+
+    #[test]
+    fn handler_returns_past_fault() {
+        let (mut proc, len) = load(
+            "li $t0, 0x7fffffff\naddi $t0, $t0, 1\nli $t1, 5\n\
+             .ktext\nmfc0 $k0, $14\naddiu $k0, $k0, 4\nmtc0 $k0, $14\neret\n",
+        );
+        while proc.active && proc.pc != ADDR_TEXT + len {
+            proc.step().unwrap();
+        }
+
+        assert_eq!(proc.cp0.epc as usize, ADDR_TEXT + 12);
+        assert_eq!(proc.regs.get_u32(REG_T0), 0x7fffffff);
+        assert_eq!(proc.regs.get_u32(REG_T1), 5);
+    }
+
+    #[test]
+    fn rem_sign() {
+        for (a, b, expected) in [(-7, 2, -1), (7, -2, 1), (7, 2, 1)] {
+            let (proc, error) = run(&format!("li $t0, {a}\nli $t1, {b}\nrem $t0, $t0, $t1\n"));
+            assert!(error.is_none());
+            assert_eq!(proc.regs.get_i32(REG_T0), expected);
+        }
+
+        let (proc, error) = run("li $t0, -7\nli $t1, 2\nremu $t0, $t0, $t1\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T0), (-7i32 as u32) % 2);
+    }
+
+    #[test]
+    fn mulo_overflow() {
+        let (proc, error) = run("li $t0, -0x8000\nli $t1, 0x10000\nmulo $t0, $t0, $t1\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_i32(REG_T0), i32::MIN);
+
+        let (_, error) = run("li $t0, 0x10000\nmulo $t0, $t0, $t0\n");
+        assert!(matches!(
+            error,
+            Some(ExecError::Unhandled(Exception::Overflow, _))
+        ));
+
+        let (proc, error) = run("li $t0, 0x10000\nli $t1, 0xffff\nmulou $t0, $t0, $t1\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T0), 0xffff0000);
+
+        let (_, error) = run("li $t0, 0x10000\nmulou $t0, $t0, $t0\n");
+        assert!(matches!(
+            error,
+            Some(ExecError::Unhandled(Exception::Overflow, _))
+        ));
+    }
+
+    #[test]
+    fn nor() {
+        let (proc, error) = run("li $t1, 0x0f0f0f0f\nli $t2, 0x00ff00ff\nnor $t0, $t1, $t2\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T0), !(0x0f0f0f0f | 0x00ff00ff));
+        assert_eq!(proc.regs.get_u32(REG_T1), 0x0f0f0f0f);
+    }
+
+    #[test]
+    fn xori() {
+        let (proc, error) = run("li $t1, 0x12345678\nxori $t0, $t1, 0xFF\nxori $t1, $t1, 0xFFFF\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T0), 0x12345687);
+        // the immediate is zero-extended, so the high half is untouched
+        assert_eq!(proc.regs.get_u32(REG_T1), 0x1234a987);
+    }
+
+    #[test]
+    fn not_and_neg() {
+        let (proc, error) = run("li $t0, 0x12345678\nnot $t1, $t0\nneg $t0, $t0\n");
+        assert!(error.is_none());
+        assert_eq!(proc.regs.get_u32(REG_T1), !0x12345678);
+        assert_eq!(proc.regs.get_i32(REG_T0), -0x12345678);
+    }
+
+    #[test]
+    fn step_over_recursive_call() {
+        let (app_tx, _app_rx) = crossbeam::channel::unbounded();
+        let (_, proc_rx) = crossbeam::channel::unbounded();
+        let mut proc = Processor::new(app_tx, proc_rx);
+        let program = assemble(
+            "li $a0, 2\njal f\nli $t1, 1\nj done\n\
+             f: addi $sp, $sp, -4\nsw $ra, 0($sp)\nbeq $a0, $zero, ret\naddi $a0, $a0, -1\njal f\n\
+             ret: lw $ra, 0($sp)\naddi $sp, $sp, 4\njr $ra\ndone: nop\n",
+            &Default::default(),
+        )
+        .unwrap();
+        proc.load(&program).unwrap();
+        // below the top, so addi doesn't overflow
+        proc.regs.set_u32(REG_SP, 0x7ffffff0);
+
+        // step over the first recursive call, which returns to `ret` after
+        // two deeper calls do
+        let (call, ret) = (ADDR_TEXT + 32, ADDR_TEXT + 36);
+        while proc.pc != call {
+            proc.step().unwrap();
+        }
+        let sp = proc.regs.get_u32(REG_SP);
+
+        proc.handle_message(ProcMessage::StepOver);
+        while proc.running {
+            proc.run_batch();
+        }
+        assert_eq!(proc.pc, ret);
+        assert_eq!(proc.regs.get_u32(REG_SP), sp);
+    }
+
+    #[test]
+    fn step_over_ignored_when_inactive() {
+        let (app_tx, _app_rx) = crossbeam::channel::unbounded();
+        let (_, proc_rx) = crossbeam::channel::unbounded();
+        let mut proc = Processor::new(app_tx, proc_rx);
+        let program = assemble("jal f\nf: nop\n", &Default::default()).unwrap();
+        proc.load(&program).unwrap();
+        proc.active = false;
+
+        proc.handle_message(ProcMessage::StepOver);
+        assert!(proc.temp_breakpoint.is_none());
+        assert!(!proc.running);
+    }
+
+    #[test]
+    fn read_char_defers_messages() {
+        let (app_tx, _app_rx) = crossbeam::channel::unbounded();
+        let (proc_tx, proc_rx) = crossbeam::channel::unbounded();
+        let mut proc = Processor::new(app_tx, proc_rx);
+
+        proc_tx.send(ProcMessage::Reset).unwrap();
+        assert!(matches!(proc.char_recv(), Err(ExecError::IoRecvError)));
+        assert!(matches!(
+            proc.deferred.make_contiguous(),
+            [ProcMessage::Reset]
+        ));
+
+        proc.handle_deferred();
+        assert!(proc.deferred.is_empty());
+    }
+
+    #[test]
+    fn run_isolated_stops() {
+        let (app_tx, _) = crossbeam::channel::unbounded();
+        let (proc_tx, proc_rx) = crossbeam::channel::unbounded();
+        let proc = Processor::new(app_tx, proc_rx);
+        let program = assemble("loop: j loop\n", &Default::default()).unwrap();
+
+        proc_tx.send(ProcMessage::Watch(ADDR_STATIC)).unwrap();
+        proc_tx.send(ProcMessage::Stop).unwrap();
+
+        let mut deferred = vec![];
+        let result = proc.run_isolated(&program, &mut deferred).unwrap();
+        assert!(result.starts_with("Ran 0 instructions, stopped"));
+        assert!(matches!(deferred[..], [ProcMessage::Watch(ADDR_STATIC)]));
+    }
+}
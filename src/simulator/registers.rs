@@ -2,9 +2,9 @@ use std::{collections::HashMap, mem::transmute};
 
 use egui_extras::{Column, TableBuilder};
 
-use crate::app::App;
+use crate::app::{highlighting::HighlightTheme, tabs::editor::LexemeHint, App, DIFF_COLOR};
 
-use super::{ADDR_HEAP, ADDR_STACK_TOP};
+use super::{Cp0, ProcSettings, ADDR_HEAP, ADDR_STACK_TOP};
 
 #[derive(Debug)]
 pub struct Registers {
@@ -73,6 +73,24 @@ impl Registers {
         }
     }
 
+    /// The conventional use of a register.
+    pub const fn usage(i: usize) -> &'static str {
+        match i {
+            0 => "Constant zero.",
+            1 => "Assembler temporary, reserved for pseudo instructions.",
+            2..=3 => "Function results and syscall codes, not preserved across calls.",
+            4..=7 => "Function arguments, not preserved across calls.",
+            8..=15 | 24..=25 => "Temporary, not preserved across calls.",
+            16..=23 => "Saved, preserved across calls.",
+            26..=27 => "Reserved for the kernel.",
+            28 => "Global pointer.",
+            29 => "Stack pointer, preserved across calls.",
+            30 => "Frame pointer, preserved across calls.",
+            31 => "Return address.",
+            _ => panic!("invalid register index"),
+        }
+    }
+
     #[rustfmt::skip]
     pub fn index(s: &str) -> Option<usize> {
         Some(match s {
@@ -114,42 +132,112 @@ impl Registers {
 
     pub fn show(app: &mut App, ui: &mut egui::Ui) {
         let regs = &app.proc.regs;
+        let baseline = app.proc.baseline.as_ref();
+
+        // starting values set in the settings, by register
+        let proc_settings = &app.settings.proc;
+        let defaults = ProcSettings::default();
+        let initial = (0..4)
+            .map(|i| {
+                (
+                    REG_A0 as usize + i,
+                    proc_settings.initial_args[i],
+                    defaults.initial_args[i],
+                )
+            })
+            .chain([(
+                REG_SP as usize,
+                proc_settings.initial_sp,
+                defaults.initial_sp,
+            )])
+            .filter(|(_, value, default)| value != default)
+            .map(|(i, value, _)| (i, value))
+            .collect::<HashMap<_, _>>();
+
+        // hi, lo and the coprocessor 0 registers follow the general purpose ones
+        let special = [
+            ("hi", String::new(), app.proc.hi),
+            ("lo", String::new(), app.proc.lo),
+        ]
+        .into_iter()
+        .chain(
+            Cp0::REGISTERS
+                .iter()
+                .map(|&(index, name)| (name, format!("c0 {index}"), app.proc.cp0.get(index))),
+        )
+        .collect::<Vec<_>>();
+
+        // the name of a register, with its number if enabled
+        let show_numbers = app.settings.show_register_numbers;
+        let label = |name: &str, num: &str| {
+            if show_numbers && !num.is_empty() {
+                format!("{name} ({num})")
+            } else {
+                name.to_owned()
+            }
+        };
 
         TableBuilder::new(ui)
             .column(Column::auto().at_least(60.0).resizable(false))
-            .column(Column::auto().at_least(30.0).resizable(false))
             .column(Column::remainder().resizable(false))
             .striped(true)
             .header(20.0, |mut header| {
                 header.col(|ui| {
                     ui.strong("Register");
                 });
-                header.col(|ui| {
-                    ui.strong("Num.");
-                });
                 header.col(|ui| {
                     ui.strong("Value");
                 });
             })
             .body(|body| {
-                body.rows(14.0, 32, |i, mut row| {
-                    row.col(|ui| {
-                        ui.monospace(format!("${}", Self::name(i)));
-                    });
+                body.rows(14.0, 32 + special.len(), |i, mut row| {
+                    if let Some((name, num, value)) = special.get(i.wrapping_sub(32)) {
+                        row.col(|ui| {
+                            ui.monospace(label(name, num));
+                        });
+                        row.col(|ui| {
+                            ui.monospace(format!("0x{value:08x}"));
+                        });
+                        return;
+                    }
+
                     row.col(|ui| {
-                        ui.monospace(format!("{i}"));
+                        let name = egui::RichText::new(label(
+                            &format!("${}", Self::name(i)),
+                            &format!("${i}"),
+                        ))
+                        .monospace();
+                        match initial.get(&i) {
+                            Some(value) => {
+                                ui.label(name.italics()).on_hover_text(format!(
+                                    "Starts at 0x{value:08x}, set in the settings"
+                                ));
+                            }
+                            None => {
+                                ui.label(name);
+                            }
+                        }
                     });
                     row.col(|ui| {
-                        if ui
-                            .add(
-                                egui::Label::new(
-                                    egui::RichText::new(format!("0x{:08x}", regs[i].0)).monospace(),
-                                )
-                                .sense(egui::Sense::click()),
-                            )
-                            .clicked()
-                        {
-                            app.memory.offset = unsafe { transmute::<_, u32>(regs[i].0) } as usize;
+                        let mut text =
+                            egui::RichText::new(format!("0x{:08x}", regs[i].0)).monospace();
+                        let before = baseline
+                            .map(|b| b.regs[i])
+                            .filter(|before| *before != regs[i]);
+                        if before.is_some() {
+                            text = text.color(DIFF_COLOR);
+                        }
+
+                        let mut response =
+                            ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                        if let Some(before) = before {
+                            response = response
+                                .on_hover_text(format!("Baseline: 0x{:08x}", before.to_u32()));
+                        }
+
+                        if response.clicked() {
+                            app.memory
+                                .set_offset(unsafe { transmute::<_, u32>(regs[i].0) } as usize);
                         }
                     });
                 })
@@ -166,3 +254,24 @@ impl Register {
         unsafe { transmute(self.0) }
     }
 }
+
+/// A hover hint for a register, showing its conventional use and value.
+pub struct RegisterHint {
+    pub index: usize,
+    pub value: Register,
+}
+
+impl LexemeHint for RegisterHint {
+    fn show(&self, ui: &mut egui::Ui, _theme: &HighlightTheme) {
+        ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(format!("${}", Registers::name(self.index)))
+                    .monospace()
+                    .strong(),
+            );
+            ui.monospace(format!("(${})", self.index));
+        });
+        ui.label(egui::RichText::new(Registers::usage(self.index)).color(egui::Color32::GRAY));
+        ui.monospace(format!("0x{:08x} ({})", self.value.to_u32(), self.value.0));
+    }
+}
@@ -0,0 +1,78 @@
+use super::ADDR_STACK_TOP;
+
+/// The default number of instructions Run mode executes between checking for
+/// messages and syncing with the app.
+pub const DEFAULT_RUN_BATCH: usize = 1000;
+
+/// The default number of instructions a single Run executes before halting.
+pub const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+/// Settings that change how the processor executes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcSettings {
+    /// The clock used by the system time syscall.
+    pub clock: ClockMode,
+
+    /// The seed of the random number syscalls, applied when the processor
+    /// is reset. If unset, a seed is taken from the system time.
+    pub seed: Option<u64>,
+
+    /// Whether or not to record an execution trace of each step.
+    pub trace: bool,
+
+    /// Whether or not memory remembers the PC of the instruction that last
+    /// wrote each word.
+    pub track_writers: bool,
+
+    /// Whether or not to record which instructions have executed, to show
+    /// coverage in the editor.
+    pub coverage: bool,
+
+    /// The number of instructions Run mode executes between checking for
+    /// messages and syncing with the app. Lower keeps the app more responsive
+    /// during heavy computation, higher runs faster.
+    pub run_batch: usize,
+
+    /// The number of instructions a single Run executes before halting, to
+    /// catch infinite loops. Zero runs without a limit.
+    pub max_steps: u64,
+
+    /// The values of `$a0`-`$a3` when the processor is reset, e.g. to pass
+    /// argc/argv-like arguments.
+    pub initial_args: [u32; 4],
+
+    /// The value of `$sp` when the processor is reset.
+    pub initial_sp: u32,
+
+    /// Whether or not branches and jumps have a delay slot: the instruction
+    /// after them executes before the branch takes effect, as on hardware.
+    pub delay_slots: bool,
+}
+
+impl Default for ProcSettings {
+    fn default() -> Self {
+        Self {
+            clock: ClockMode::default(),
+            seed: None,
+            trace: false,
+            track_writers: false,
+            coverage: false,
+            run_batch: DEFAULT_RUN_BATCH,
+            max_steps: DEFAULT_MAX_STEPS,
+            initial_args: [0; 4],
+            initial_sp: ADDR_STACK_TOP as u32,
+            delay_slots: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// The system time syscall reads the wall-clock time.
+    #[default]
+    System,
+
+    /// The system time syscall reads a clock starting at zero that advances
+    /// by a fixed number of milliseconds on each call, for reproducible runs.
+    Fixed(u64),
+}
@@ -1,24 +1,76 @@
-use std::{collections::HashMap, sync::Arc, thread};
+use std::{collections::HashMap, fs::File, io::BufWriter, path::PathBuf, sync::Arc};
 
 use parking_lot::RwLock;
 
-use crate::assembler::parser::Parser;
+use crate::assembler::AssembledProgram;
 
-use super::{LoadContext, Memory, Processor, Register};
+use super::{Cp0, ExecError, Memory, ProcSettings, Processor, Register, REG_SP};
 
 /// Messages from the app to the processor.
+#[derive(Debug)]
 pub enum ProcMessage {
     /// Reset the processor state.
     Reset,
 
-    /// Load some source code into the processor.
-    Load(String),
+    /// Reset the registers and PC to rerun the loaded program, keeping
+    /// memory as is.
+    Restart,
+
+    /// Load an assembled program into the processor.
+    Load(AssembledProgram),
+
+    /// Replace only the text segment with that of an assembled program,
+    /// preserving the rest of memory.
+    ReloadCode(AssembledProgram),
 
     /// Step the processor.
     Step,
 
+    /// Step the processor until the PC reaches another source line, so a
+    /// pseudo instruction steps as a whole.
+    StepLine,
+
+    /// Step the processor a number of times, stopping early if it halts or
+    /// errors, and syncing once at the end.
+    StepMany(usize),
+
+    /// Step the processor, running through subroutine calls as if they
+    /// were a single instruction.
+    StepOver,
+
+    /// Step the processor continuously until it is stopped, exits, or
+    /// hits a watchpoint.
+    Run,
+
+    /// Stop Run mode.
+    Stop,
+
+    /// Watch an address, halting Run mode when it is written to.
+    Watch(usize),
+
+    /// Stop watching an address.
+    Unwatch(usize),
+
+    /// Save the execution trace to a file.
+    SaveTrace(PathBuf),
+
+    /// Execute instruction words typed into the console against the
+    /// current state.
+    Execute(Vec<u32>),
+
+    /// Run an assembled selection of the editor in a throwaway processor,
+    /// starting from the current registers.
+    RunIsolated(AssembledProgram),
+
     /// Send some stdin to the processor.
     Io(String),
+
+    /// Send a single keystroke to the processor while it waits on a
+    /// character read.
+    Key(char),
+
+    /// Update the processor settings.
+    Settings(ProcSettings),
 }
 
 /// Messages from the processor to the app.
@@ -29,8 +81,11 @@ pub enum AppMessage {
     /// Send messages to the app log.
     Log(String),
 
-    /// Notify the app of the PC addr <-> line relationship.
-    PcLines(HashMap<usize, u32>),
+    /// The processor started or stopped waiting on a character read.
+    AwaitingChar(bool),
+
+    /// The result of instructions executed from the console.
+    Console(String),
 
     /// Something about the processor state has changed that we want
     /// to see reflected in the app.
@@ -41,7 +96,17 @@ pub enum AppMessage {
 pub struct ProcSync {
     pub pc: usize,
     pub regs: RegSync,
+    pub hi: u32,
+    pub lo: u32,
+    pub cp0: Cp0,
+    pub loaded: bool,
     pub active: bool,
+    pub running: bool,
+    pub inst_count: u64,
+
+    /// The addresses executed since the last sync, or all of them on a hard
+    /// sync.
+    pub executed: Vec<usize>,
 }
 
 pub enum RegSync {
@@ -62,66 +127,48 @@ pub struct ProcSpawn {
 }
 
 impl Processor {
+    /// Spawn a processor on its own thread. The thread only hands messages to
+    /// [`Processor::handle_message`] and calls [`Processor::run_batch`] while
+    /// running, so a build without threads can drive a processor the same way
+    /// from the frame loop, see [`Processor::local`].
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn spawn() -> ProcSpawn {
+        use crossbeam::channel::TryRecvError;
+
         let (proc_tx, proc_rx) = crossbeam::channel::unbounded::<ProcMessage>();
         let (app_tx, app_rx) = crossbeam::channel::unbounded::<AppMessage>();
 
         let mut proc = Self::new(app_tx.clone(), proc_rx.clone());
         let mem = proc.clone_mem_arc();
 
-        thread::spawn(move || {
+        std::thread::spawn(move || {
             // sync once with the editor
             app_tx.send(AppMessage::Sync(proc.sync_hard())).unwrap();
 
-            while let Ok(message) = proc_rx.recv() {
-                match message {
-                    ProcMessage::Reset => {
-                        app_tx.send(AppMessage::Sync(proc.reset())).unwrap();
+            loop {
+                // don't block on messages while in Run mode
+                let message = if proc.running {
+                    match proc_rx.try_recv() {
+                        Ok(message) => Some(message),
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => break,
                     }
-
-                    ProcMessage::Load(body) => {
-                        let parser = Parser::new(&body);
-                        let parsed = match parser.parse() {
-                            Ok(p) => p,
-                            Err(e) => {
-                                app_tx
-                                    .send(AppMessage::Log(format!("Parse error: {e}")))
-                                    .unwrap();
-                                return;
-                            }
-                        };
-                        match LoadContext::new(&mut proc, &parsed).load() {
-                            Ok(map) => {
-                                app_tx.send(AppMessage::Sync(proc.sync_hard())).unwrap();
-                                app_tx.send(AppMessage::PcLines(map)).unwrap();
-                                app_tx
-                                    .send(AppMessage::Log("Processor loaded".to_string()))
-                                    .unwrap();
-                            }
-                            Err(e) => {
-                                app_tx
-                                    .send(AppMessage::Log(format!("Load error: {e}")))
-                                    .unwrap();
-                            }
-                        }
+                } else {
+                    match proc_rx.recv() {
+                        Ok(message) => Some(message),
+                        Err(_) => break,
                     }
+                };
 
-                    ProcMessage::Step => match proc.step() {
-                        Ok(()) => {
-                            app_tx.send(AppMessage::Sync(proc.sync())).unwrap();
-                            app_tx
-                                .send(AppMessage::Log(format!("New PC: {}", proc.pc)))
-                                .unwrap();
-                        }
-                        Err(e) => {
-                            app_tx
-                                .send(AppMessage::Log(format!("Step error: {e}")))
-                                .unwrap();
-                        }
-                    },
+                if let Some(message) = message {
+                    proc.handle_message(message);
+                }
 
-                    ProcMessage::Io(_) => (),
+                if proc.running {
+                    proc.run_batch();
                 }
+
+                proc.handle_deferred();
             }
         });
 
@@ -132,15 +179,407 @@ impl Processor {
         }
     }
 
-    pub fn io_recv(&mut self) -> Result<String, ()> {
-        while let Ok(message) = self.proc_rx.recv() {
-            match message {
+    /// Create a processor for a build without threads, to be driven from the
+    /// frame loop with [`Processor::pump`].
+    #[cfg(target_arch = "wasm32")]
+    pub fn local() -> (Self, ProcSpawn) {
+        let (proc_tx, proc_rx) = crossbeam::channel::unbounded::<ProcMessage>();
+        let (app_tx, app_rx) = crossbeam::channel::unbounded::<AppMessage>();
+
+        let mut proc = Self::new(app_tx.clone(), proc_rx);
+        let mem = proc.clone_mem_arc();
+
+        // sync once with the editor
+        app_tx.send(AppMessage::Sync(proc.sync_hard())).unwrap();
+
+        (
+            proc,
+            ProcSpawn {
+                proc_tx,
+                app_rx,
+                mem,
+            },
+        )
+    }
+
+    /// Handle the messages sent since the last call, then run a batch of
+    /// steps in Run mode.
+    #[cfg(target_arch = "wasm32")]
+    pub fn pump(&mut self) {
+        while let Ok(message) = self.proc_rx.try_recv() {
+            self.handle_message(message);
+        }
+
+        // retry a step that waited for input, as Run mode does
+        if !self.pending_input.is_empty() {
+            if let Some(message) = self.awaiting_step.take() {
+                self.handle_message(message);
+            }
+        }
+
+        if self.running {
+            self.run_batch();
+        }
+
+        self.handle_deferred();
+    }
+
+    /// Handle the messages that arrived while a read syscall waited for
+    /// input.
+    pub fn handle_deferred(&mut self) {
+        while let Some(message) = self.deferred.pop_front() {
+            self.handle_message(message);
+        }
+    }
+
+    /// Keep a step message that stopped at a read syscall to retry once
+    /// input is sent, in a build without threads.
+    #[cfg(target_arch = "wasm32")]
+    fn await_input(&mut self, message: ProcMessage) {
+        if self.awaiting_step.is_none() {
+            self.app_tx
+                .send(AppMessage::Log("Waiting for input".to_string()))
+                .unwrap();
+        }
+
+        self.awaiting_step = Some(message);
+        let sync = self.sync();
+        self.app_tx.send(AppMessage::Sync(sync)).unwrap();
+    }
+
+    /// Handle a message from the app.
+    pub fn handle_message(&mut self, message: ProcMessage) {
+        let app_tx = self.app_tx.clone();
+
+        match message {
+            ProcMessage::Reset => {
+                app_tx.send(AppMessage::Sync(self.reset())).unwrap();
+            }
+
+            ProcMessage::Restart => {
+                app_tx.send(AppMessage::Sync(self.restart())).unwrap();
+                app_tx
+                    .send(AppMessage::Log(
+                        "Processor restarted: registers and PC were reset, memory was kept"
+                            .to_string(),
+                    ))
+                    .unwrap();
+            }
+
+            ProcMessage::Load(program) => match self.load(&program) {
+                Ok(sync) => {
+                    app_tx.send(AppMessage::Sync(sync)).unwrap();
+                    app_tx
+                        .send(AppMessage::Log(format!(
+                            "Processor loaded: {}",
+                            program.summary()
+                        )))
+                        .unwrap();
+                }
+                Err(e) => {
+                    app_tx
+                        .send(AppMessage::Log(format!("Load error: {e}")))
+                        .unwrap();
+                }
+            },
+
+            ProcMessage::ReloadCode(program) => match self.reload_code(&program) {
+                Ok(sync) => {
+                    app_tx.send(AppMessage::Sync(sync)).unwrap();
+                    app_tx
+                        .send(AppMessage::Log(
+                            "Processor reloaded code only: the text segment was replaced and registers reset, while static data, heap and stack were kept".to_string(),
+                        ))
+                        .unwrap();
+                }
+                Err(e) => {
+                    app_tx
+                        .send(AppMessage::Log(format!("Load error: {e}")))
+                        .unwrap();
+                }
+            },
+
+            ProcMessage::StepOver if !self.loaded || !self.active => (),
+
+            ProcMessage::StepOver if self.next_is_call() => {
+                // run until the call returns to this stack frame
+                self.temp_breakpoint = Some((self.return_addr(), self.regs.get_u32(REG_SP)));
+                self.running = self.loaded && self.active;
+                self.run_start = self.inst_count;
+            }
+
+            ProcMessage::Step | ProcMessage::StepOver => match self.step() {
+                Ok(()) => {
+                    app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+                    app_tx
+                        .send(AppMessage::Log(format!("New PC: {}", self.pc)))
+                        .unwrap();
+                }
+                #[cfg(target_arch = "wasm32")]
+                Err(ExecError::AwaitingInput) => self.await_input(ProcMessage::Step),
+                Err(e) => {
+                    app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+                    app_tx
+                        .send(AppMessage::Log(format!("Step error: {e}")))
+                        .unwrap();
+                }
+            },
+
+            ProcMessage::StepLine => {
+                let start = self.pc;
+                let line = self.addr_lines.get(&start).copied();
+
+                loop {
+                    if !self.loaded || !self.active {
+                        break;
+                    }
+
+                    if let Err(e) = self.step() {
+                        #[cfg(target_arch = "wasm32")]
+                        if let ExecError::AwaitingInput = e {
+                            // the rest of the line runs once input arrives
+                            self.await_input(ProcMessage::StepLine);
+                            return;
+                        }
+
+                        app_tx
+                            .send(AppMessage::Log(format!("Step error: {e}")))
+                            .unwrap();
+                        break;
+                    }
+
+                    // stop on another line, or when a line loops back to itself
+                    if line.is_none()
+                        || self.addr_lines.get(&self.pc) != line.as_ref()
+                        || self.pc == start
+                    {
+                        break;
+                    }
+                }
+
+                app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+                app_tx
+                    .send(AppMessage::Log(format!("New PC: {}", self.pc)))
+                    .unwrap();
+            }
+
+            ProcMessage::StepMany(count) => {
+                #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))]
+                for remaining in (1..=count).rev() {
+                    if !self.loaded || !self.active {
+                        break;
+                    }
+
+                    if let Err(e) = self.step() {
+                        #[cfg(target_arch = "wasm32")]
+                        if let ExecError::AwaitingInput = e {
+                            self.await_input(ProcMessage::StepMany(remaining));
+                            return;
+                        }
+
+                        app_tx
+                            .send(AppMessage::Log(format!("Step error: {e}")))
+                            .unwrap();
+                        break;
+                    }
+                }
+
+                app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+                app_tx
+                    .send(AppMessage::Log(format!("New PC: {}", self.pc)))
+                    .unwrap();
+            }
+
+            ProcMessage::Run => {
+                self.running = self.loaded && self.active;
+                self.run_start = self.inst_count;
+            }
+
+            ProcMessage::Stop => {
+                self.running = false;
+                self.temp_breakpoint = None;
+                #[cfg(target_arch = "wasm32")]
+                {
+                    self.awaiting_step = None;
+                }
+                app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+            }
+
+            ProcMessage::Watch(addr) => {
+                self.watchpoints.insert(addr);
+            }
+
+            ProcMessage::Unwatch(addr) => {
+                self.watchpoints.remove(&addr);
+            }
+
+            ProcMessage::SaveTrace(path) => {
+                let result =
+                    File::create(&path).and_then(|file| self.write_trace(BufWriter::new(file)));
+
+                let message = match result {
+                    Ok(()) if self.trace.is_empty() && !self.settings.trace => {
+                        "No trace was recorded; enable tracing in the settings".to_string()
+                    }
+                    Ok(()) => format!(
+                        "Saved {} trace steps to {}",
+                        self.trace.len(),
+                        path.display()
+                    ),
+                    Err(e) => format!("Failed to save trace: {e}"),
+                };
+                app_tx.send(AppMessage::Log(message)).unwrap();
+            }
+
+            ProcMessage::Execute(words) => {
+                let result = match self.execute_words(&words) {
+                    Ok(changes) => changes,
+                    Err(e) => format!("Error: {e}"),
+                };
+                app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+                app_tx.send(AppMessage::Console(result)).unwrap();
+            }
+
+            ProcMessage::RunIsolated(program) => {
+                let mut deferred = vec![];
+                let result = match self.run_isolated(&program, &mut deferred) {
+                    Ok(result) => result,
+                    Err(e) => format!("Error: {e}"),
+                };
+                app_tx.send(AppMessage::Console(result)).unwrap();
+
+                for message in deferred {
+                    self.handle_message(message);
+                }
+            }
+
+            ProcMessage::Settings(settings) => {
+                self.set_settings(settings);
+            }
+
+            // without threads, input is kept until a read syscall asks for it
+            #[cfg(target_arch = "wasm32")]
+            input @ (ProcMessage::Io(_) | ProcMessage::Key(_)) => {
+                self.pending_input.push_back(input);
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            ProcMessage::Io(_) | ProcMessage::Key(_) => (),
+        }
+    }
+
+    /// Execute a batch of steps in Run mode, then sync with the app. Run mode
+    /// stops early when the processor halts or hits a breakpoint.
+    pub fn run_batch(&mut self) {
+        let app_tx = self.app_tx.clone();
+
+        for _ in 0..self.settings.run_batch.max(1) {
+            match self.step() {
+                Ok(()) => (),
+
+                // keep running once input arrives
+                Err(ExecError::AwaitingInput) => break,
+
+                Err(e) => {
+                    self.running = false;
+                    app_tx
+                        .send(AppMessage::Log(format!("Step error: {e}")))
+                        .unwrap();
+                }
+            }
+
+            if let Some((addr, sp)) = self.temp_breakpoint {
+                if self.pc == addr && self.regs.get_u32(REG_SP) >= sp {
+                    self.running = false;
+                }
+            }
+
+            let max_steps = self.settings.max_steps;
+            if self.running
+                && max_steps != 0
+                && self.inst_count.saturating_sub(self.run_start) >= max_steps
+            {
+                self.running = false;
+                app_tx
+                    .send(AppMessage::Log(format!(
+                        "Execution limit of {max_steps} instructions reached, possible infinite loop"
+                    )))
+                    .unwrap();
+            }
+
+            if !self.running || !self.active {
+                self.running = false;
+                self.temp_breakpoint = None;
+                break;
+            }
+        }
+
+        app_tx.send(AppMessage::Sync(self.sync())).unwrap();
+    }
+
+    /// Block until the next message for a read syscall.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recv_input(&mut self) -> Result<ProcMessage, ExecError> {
+        self.proc_rx.recv().map_err(|_| ExecError::IoRecvError)
+    }
+
+    /// Take the next message for a read syscall. Without threads this can't
+    /// block, so it fails with [`ExecError::AwaitingInput`] until input is
+    /// sent.
+    #[cfg(target_arch = "wasm32")]
+    fn recv_input(&mut self) -> Result<ProcMessage, ExecError> {
+        self.pending_input
+            .pop_front()
+            .or_else(|| self.proc_rx.try_recv().ok())
+            .ok_or(ExecError::AwaitingInput)
+    }
+
+    pub fn io_recv(&mut self) -> Result<String, ExecError> {
+        loop {
+            match self.recv_input()? {
                 ProcMessage::Io(string) => return Ok(string),
                 ProcMessage::Step => continue,
-                _ => return Err(()),
+                ProcMessage::Settings(settings) => self.set_settings(settings),
+
+                // e.g. a Reset, which ends the read
+                message => {
+                    self.deferred.push_back(message);
+                    return Err(ExecError::IoRecvError);
+                }
             }
         }
+    }
 
-        Err(())
+    /// Block until a character of input is available. Lines sent with
+    /// [`ProcMessage::Io`] are read a character at a time, ending in `\n`.
+    pub fn char_recv(&mut self) -> Result<char, ExecError> {
+        if let Some(c) = self.input_chars.pop_front() {
+            return Ok(c);
+        }
+
+        let _ = self.app_tx.send(AppMessage::AwaitingChar(true));
+
+        let result = loop {
+            match self.recv_input() {
+                Ok(ProcMessage::Key(c)) => break Ok(c),
+                Ok(ProcMessage::Io(string)) => {
+                    self.input_chars.extend(string.chars().chain(['\n']));
+                    break self.input_chars.pop_front().ok_or(ExecError::IoRecvError);
+                }
+                Ok(ProcMessage::Step) => continue,
+                Ok(ProcMessage::Settings(settings)) => self.set_settings(settings),
+                Ok(message) => {
+                    self.deferred.push_back(message);
+                    break Err(ExecError::IoRecvError);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        // still waiting without threads, so the app keeps sending keys
+        if !matches!(result, Err(ExecError::AwaitingInput)) {
+            let _ = self.app_tx.send(AppMessage::AwaitingChar(false));
+        }
+        result
     }
 }
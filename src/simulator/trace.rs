@@ -0,0 +1,43 @@
+use std::fmt;
+
+use crate::assembler::disasm;
+
+use super::Registers;
+
+/// The maximum number of steps kept in the execution trace. Older steps are
+/// dropped first.
+pub const TRACE_LIMIT: usize = 100_000;
+
+/// A single step of the execution trace.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    /// The address of the executed instruction.
+    pub pc: usize,
+
+    /// The executed instruction word.
+    pub word: u32,
+
+    /// The registers written by the instruction, with their new values.
+    pub regs: Vec<(usize, i32)>,
+
+    /// The memory written by the instruction, as address, length in bytes,
+    /// and new value.
+    pub store: Option<(usize, usize, u32)>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inst = disasm::disassemble(self.word).unwrap_or_else(|| "???".to_owned());
+        let mut line = format!("{:08x}: {:08x}  {inst:<24}", self.pc, self.word);
+
+        for (index, value) in self.regs.iter() {
+            line += &format!(" ${}=0x{:08x}", Registers::name(*index), *value as u32);
+        }
+
+        if let Some((addr, len, value)) = self.store {
+            line += &format!(" [0x{addr:08x}]=0x{value:0width$x}", width = len * 2);
+        }
+
+        f.write_str(line.trim_end())
+    }
+}
@@ -7,7 +7,7 @@ pub struct IndexedChars<'a> {
     offset: usize,
 }
 
-impl<'a> Iterator for IndexedChars<'a> {
+impl Iterator for IndexedChars<'_> {
     type Item = (usize, char);
 
     fn next(&mut self) -> Option<Self::Item> {
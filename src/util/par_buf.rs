@@ -11,6 +11,7 @@ pub struct ParBuf<T> {
     rx: Receiver<T>,
     pub tx: Sender<T>,
     limit: Option<NonZeroUsize>,
+    dropped: usize,
 }
 
 impl<T> ParBuf<T> {
@@ -21,6 +22,7 @@ impl<T> ParBuf<T> {
             rx,
             tx,
             limit: None,
+            dropped: 0,
         }
     }
 
@@ -31,17 +33,43 @@ impl<T> ParBuf<T> {
         self
     }
 
+    /// Change the limit of the parallel buffer, dropping the oldest
+    /// items if there are now too many.
+    /// Will panic if the limit is zero.
+    pub fn set_limit(&mut self, limit: usize) {
+        let limit = NonZeroUsize::new(limit).expect("non-zero ParBuf limit");
+        self.limit = Some(limit);
+
+        while self.vec.len() > limit.into() {
+            self.vec.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Clear the buffer, including the count of dropped items.
+    pub fn clear(&mut self) {
+        self.vec.clear();
+        self.dropped = 0;
+    }
+
     pub fn update(&mut self) {
         while let Ok(item) = self.rx.try_recv() {
             if let Some(limit) = self.limit {
                 while self.vec.len() >= limit.into() {
                     self.vec.pop_front();
+                    self.dropped += 1;
                 }
             }
 
             self.vec.push_back(item);
         }
     }
+
+    /// The number of items dropped from the front of the buffer
+    /// because the limit was exceeded.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
 }
 
 impl<T> Default for ParBuf<T> {
@@ -63,3 +91,40 @@ impl<T> DerefMut for ParBuf<T> {
         &mut self.vec
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrink_limit() {
+        let mut buf = ParBuf::new().limit(5);
+        for i in 0..5 {
+            buf.tx.send(i).unwrap();
+        }
+        buf.update();
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+
+        buf.set_limit(2);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(buf.dropped(), 3);
+
+        buf.tx.send(5).unwrap();
+        buf.update();
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(buf.dropped(), 4);
+    }
+
+    #[test]
+    fn grow_limit() {
+        let mut buf = ParBuf::new().limit(2);
+        for i in 0..3 {
+            buf.tx.send(i).unwrap();
+        }
+        buf.update();
+
+        buf.set_limit(4);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(buf.dropped(), 1);
+    }
+}